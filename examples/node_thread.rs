@@ -0,0 +1,20 @@
+//! A module for the Node.js smoke test in `tests/node.rs`, which spawns and joins one thread.
+//!
+//! Build it with shared memory and generate its glue with `wasm-bindgen --target web`:
+//!
+//! ```sh
+//! RUSTFLAGS="-C target-feature=+atomics,+bulk-memory" cargo +nightly build --example node_thread \
+//!     --target wasm32-unknown-unknown -Z build-std=std,panic_abort
+//! wasm-bindgen --target web --out-dir target/node_thread \
+//!     target/wasm32-unknown-unknown/debug/examples/node_thread.wasm
+//! ```
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Spawns a thread that computes `x + 1` and returns its result.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn spawn_and_join(x: u32) -> Result<u32, wasm_ca_rs::error::Error> {
+    wasm_ca_rs::thread::thread_spawn(move || x + 1)?.join()
+}
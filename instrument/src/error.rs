@@ -0,0 +1,36 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The input is not a valid wasm module or the output could not be produced.
+    Wasm(String),
+    /// The module cannot be instrumented (e.g., a hook has an unexpected signature).
+    UnsupportedModule(String),
+    /// An I/O operation failed.
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Wasm(e) => write!(f, "invalid wasm module: {e}"),
+            Error::UnsupportedModule(e) => write!(f, "unsupported module: {e}"),
+            Error::Io(e) => write!(f, "i/o error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
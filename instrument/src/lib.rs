@@ -0,0 +1,798 @@
+//! Rewrites a wasm module such that it reports its memory accesses and lock operations to the
+//! `wasm_abi` hooks of the wasm-ca runtime.
+//!
+//! Every hook receives the location of the instrumented instruction as `(fidx, iidx)`, where
+//! `fidx` is the index of the function in the original module and `iidx` is the index of the
+//! instruction in a pre-order walk over the original function body.
+
+use std::collections::{HashMap, HashSet};
+
+use walrus::{
+    ir::{
+        BinaryOp, Binop, Call, Const, GlobalGet, Instr, InstrLocId, InstrSeqId, LegacyCatch, LoadSimdKind,
+        LocalGet, LocalSet, LocalTee, MemoryCopy, MemoryFill, MemoryInit, StoreKind, Value,
+    },
+    ExportItem, FunctionId, FunctionKind, GlobalId, LocalFunction, LocalId, MemoryId, Module,
+    ModuleLocals, ValType,
+};
+use wasm_ca_rs::location::{Location, LocationMap};
+
+mod error;
+
+pub use error::Error;
+
+/// The module name used for hooks that have to be imported.
+pub const HOOK_MODULE: &str = "wasm_ca";
+
+const READ_HOOK: &str = "read_event";
+const WRITE_HOOK: &str = "write_event";
+
+// Calls to these runtime intrinsics are preceded by a call to the given hook,
+// which receives the intrinsic's arguments (if it has one) and the location of the call.
+const INTRINSICS: [(&str, &str, usize); 7] = [
+    ("start_lock", "request_event", 1),
+    ("finish_lock", "aquire_event", 1),
+    ("start_unlock", "release_event", 1),
+    ("spawn_thread", "fork_event", 1),
+    ("join_thread", "join_event", 1),
+    ("begin_atomic", "begin_event", 0),
+    ("end_atomic", "end_event", 0),
+];
+
+// Calls to this intrinsic are preceded by a call to its hook, which receives the values of the
+// linker globals that describe the calling thread's stack and TLS block instead of a location.
+const THREAD_MEMORY: (&str, &str) = ("thread_memory", "thread_memory_event");
+const THREAD_MEMORY_GLOBALS: [&str; 3] = ["__stack_pointer", "__tls_base", "__tls_size"];
+
+// The runtime's reentrancy guard, which hooks enter before they record anything. Their accesses,
+// and those of the functions they call, would reach a hook before the guard is entered.
+const GUARD: [&str; 2] = ["enter_tracer", "exit_tracer"];
+
+// Functions generated by the linker, which run before the thread's TLS block (and with it the
+// guard) is set up.
+const LINKER_FUNCTIONS: [&str; 3] = ["__wasm_init_memory", "__wasm_init_tls", "__wasm_apply_data_relocs"];
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Module name to import the hooks from if the module does not define them itself.
+    pub hook_module: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hook_module: String::from(HOOK_MODULE),
+        }
+    }
+}
+
+pub struct Instrumented {
+    /// The rewritten module.
+    pub module: Vec<u8>,
+    /// The original location of every `(fidx, iidx)` pair passed to a hook.
+    pub locations: LocationMap,
+}
+
+/// Instruments the wasm module `wasm` and returns the rewritten module.
+pub fn instrument(wasm: &[u8], config: &Config) -> Result<Instrumented, Error> {
+    let mut module = Module::from_buffer(wasm).map_err(|e| Error::Wasm(format!("{e:#}")))?;
+    let code_section_start = code_section_start(wasm)?;
+
+    let Some(memory) = main_memory(&module)? else {
+        // Without a memory there is nothing to trace.
+        return Ok(Instrumented {
+            module: module.emit_wasm(),
+            locations: LocationMap::new(),
+        });
+    };
+
+    let read = hook(&mut module, READ_HOOK, 4, config)?;
+    let write = hook(&mut module, WRITE_HOOK, 4, config)?;
+    let mut intrinsics = HashMap::new();
+    for (intrinsic, hook_name, args) in INTRINSICS {
+        if let Some(intrinsic) = find_function(&module, intrinsic) {
+            let hook = hook(&mut module, hook_name, args + 2, config)?;
+            intrinsics.insert(intrinsic, (hook, args));
+        }
+    }
+    // Without the globals (e.g., in modules without threads) the runtime learns nothing
+    let globals: Option<Vec<_>> = THREAD_MEMORY_GLOBALS
+        .iter()
+        .map(|name| find_global(&module, name))
+        .collect();
+    let thread_memory = match (find_function(&module, THREAD_MEMORY.0), globals) {
+        (Some(intrinsic), Some(globals)) => {
+            let hook = hook(&mut module, THREAD_MEMORY.1, globals.len(), config)?;
+            Some((intrinsic, hook, globals))
+        }
+        _ => None,
+    };
+
+    // The hooks must not observe their own memory accesses, otherwise they would call themselves
+    // recursively. Everything they call is instrumented and dropped by the guard at runtime.
+    let mut skipped: HashSet<FunctionId> = [read, write].into();
+    skipped.extend(intrinsics.iter().flat_map(|(intrinsic, (hook, _))| [*intrinsic, *hook]));
+    skipped.extend(thread_memory.iter().flat_map(|(intrinsic, hook, _)| [*intrinsic, *hook]));
+    skipped.extend(module.start);
+    skipped.extend(LINKER_FUNCTIONS.iter().filter_map(|name| find_function(&module, name)));
+    let guard: Vec<_> = GUARD.iter().filter_map(|name| find_function(&module, name)).collect();
+    skipped.extend(called(&module, &guard));
+
+    let hooks = Hooks {
+        memory,
+        read,
+        write,
+        intrinsics,
+        thread_memory,
+    };
+    let names: HashMap<FunctionId, String> = module
+        .funcs
+        .iter()
+        .filter_map(|func| Some((func.id(), func.name.clone()?)))
+        .collect();
+
+    let mut locations = LocationMap::new();
+    let Module { funcs, locals, .. } = &mut module;
+    for (id, func) in funcs.iter_local_mut() {
+        if skipped.contains(&id) {
+            continue;
+        }
+        let mut rewriter = Rewriter {
+            hooks: &hooks,
+            locals: &mut *locals,
+            temps: HashMap::new(),
+            fidx: id.index() as i32,
+            iidx: 0,
+            offsets: Vec::new(),
+        };
+        let entry = func.entry_block();
+        rewriter.rewrite_seq(func, entry);
+
+        for (iidx, loc) in rewriter.offsets {
+            let location = Location {
+                offset: u64::from(loc.data()).saturating_sub(code_section_start),
+                function: names.get(&id).cloned(),
+            };
+            locations.insert((id.index(), iidx as usize), location);
+        }
+    }
+
+    Ok(Instrumented {
+        module: module.emit_wasm(),
+        locations,
+    })
+}
+
+// Instruction locations of walrus are offsets into the module, DWARF however
+// expects them to be relative to the start of the code section.
+fn code_section_start(wasm: &[u8]) -> Result<u64, Error> {
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        if let wasmparser::Payload::CodeSectionStart { range, .. } =
+            payload.map_err(|e| Error::Wasm(e.to_string()))?
+        {
+            return Ok(range.start as u64);
+        }
+    }
+    Ok(0)
+}
+
+struct Hooks {
+    memory: MemoryId,
+    read: FunctionId,
+    write: FunctionId,
+    // The hook and the number of arguments of each intrinsic
+    intrinsics: HashMap<FunctionId, (FunctionId, usize)>,
+    // The intrinsic, its hook and the globals passed to it
+    thread_memory: Option<(FunctionId, FunctionId, Vec<GlobalId>)>,
+}
+
+fn main_memory(module: &Module) -> Result<Option<MemoryId>, Error> {
+    match module.memories.iter().next() {
+        Some(memory) if memory.memory64 => Err(Error::UnsupportedModule(String::from(
+            "64-bit memories are not supported",
+        ))),
+        Some(memory) => Ok(Some(memory.id())),
+        None => Ok(None),
+    }
+}
+
+fn find_function(module: &Module, name: &str) -> Option<FunctionId> {
+    module
+        .exports
+        .iter()
+        .find_map(|export| match export.item {
+            ExportItem::Function(id) if export.name == name => Some(id),
+            _ => None,
+        })
+        .or_else(|| module.funcs.by_name(name))
+}
+
+fn find_global(module: &Module, name: &str) -> Option<GlobalId> {
+    module
+        .exports
+        .iter()
+        .find_map(|export| match export.item {
+            ExportItem::Global(id) if export.name == name => Some(id),
+            _ => None,
+        })
+        .or_else(|| {
+            module
+                .globals
+                .iter()
+                .find(|global| global.name.as_deref() == Some(name))
+                .map(|global| global.id())
+        })
+}
+
+// Looks up the hook `name` in the module (i.e., when the runtime is linked into it)
+// and imports it from the hook module otherwise.
+fn hook(
+    module: &mut Module,
+    name: &str,
+    params: usize,
+    config: &Config,
+) -> Result<FunctionId, Error> {
+    let expected = vec![ValType::I32; params];
+
+    if let Some(id) = find_function(module, name) {
+        let ty = module.types.get(module.funcs.get(id).ty());
+        if ty.params() != expected.as_slice() || !ty.results().is_empty() {
+            return Err(Error::UnsupportedModule(format!(
+                "hook '{name}' does not have the expected signature"
+            )));
+        }
+        return Ok(id);
+    }
+
+    let ty = module.types.add(&expected, &[]);
+    Ok(module.add_import_func(&config.hook_module, name, ty).0)
+}
+
+// Computes all functions called directly from `roots`, including the roots themselves.
+fn called(module: &Module, roots: &[FunctionId]) -> HashSet<FunctionId> {
+    let mut visited: HashSet<FunctionId> = HashSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let FunctionKind::Local(func) = &module.funcs.get(id).kind else {
+            continue;
+        };
+
+        let mut seqs = vec![func.entry_block()];
+        while let Some(seq) = seqs.pop() {
+            for (instr, _) in &func.block(seq).instrs {
+                seqs.extend(nested_seqs(instr));
+                match instr {
+                    Instr::Call(Call { func }) => stack.push(*func),
+                    Instr::ReturnCall(call) => stack.push(call.func),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    visited
+}
+
+fn nested_seqs(instr: &Instr) -> Vec<InstrSeqId> {
+    match instr {
+        Instr::Block(block) => vec![block.seq],
+        Instr::Loop(block) => vec![block.seq],
+        Instr::TryTable(block) => vec![block.seq],
+        Instr::IfElse(block) => vec![block.consequent, block.alternative],
+        Instr::Try(block) => std::iter::once(block.seq)
+            .chain(block.catches.iter().filter_map(|catch| match catch {
+                LegacyCatch::Catch { handler, .. } | LegacyCatch::CatchAll { handler } => {
+                    Some(*handler)
+                }
+                LegacyCatch::Delegate { .. } => None,
+            }))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Describes the memory access performed by an instruction.
+struct Access {
+    hook: FunctionId,
+    offset: u64,
+    n: u32,
+    // The operands that are on the stack above the address.
+    operands: Vec<ValType>,
+}
+
+struct Rewriter<'a> {
+    hooks: &'a Hooks,
+    locals: &'a mut ModuleLocals,
+    // Scratch locals of the current function, keyed by type and operand position.
+    temps: HashMap<(ValType, usize), LocalId>,
+    fidx: i32,
+    iidx: i32,
+    // The original location of every instrumented instruction.
+    offsets: Vec<(i32, InstrLocId)>,
+}
+
+impl Rewriter<'_> {
+    fn rewrite_seq(&mut self, func: &mut LocalFunction, seq: InstrSeqId) {
+        let instrs = std::mem::take(&mut func.block_mut(seq).instrs);
+        let mut rewritten = Vec::with_capacity(instrs.len());
+
+        for (instr, loc) in instrs {
+            let iidx = self.iidx;
+            self.iidx += 1;
+
+            if let Some(access) = self.access(&instr) {
+                self.emit_access(&mut rewritten, access, iidx);
+                self.offsets.push((iidx, loc));
+            } else if let Some(accesses) = self.bulk_access(&instr) {
+                self.emit_bulk_access(&mut rewritten, &accesses, iidx);
+                self.offsets.push((iidx, loc));
+            } else if let Instr::Call(Call { func }) = &instr {
+                if let Some((hook, args)) = self.hooks.intrinsics.get(func) {
+                    self.emit_intrinsic(&mut rewritten, *hook, *args, iidx);
+                    self.offsets.push((iidx, loc));
+                } else if let Some((_, hook, globals)) =
+                    self.hooks.thread_memory.as_ref().filter(|(intrinsic, ..)| intrinsic == func)
+                {
+                    rewritten.extend(
+                        globals
+                            .iter()
+                            .map(|global| Instr::from(GlobalGet { global: *global }))
+                            .chain([Call { func: *hook }.into()])
+                            .map(|instr| (instr, InstrLocId::default())),
+                    );
+                }
+            }
+
+            let nested = nested_seqs(&instr);
+            rewritten.push((instr, loc));
+            for seq in nested {
+                self.rewrite_seq(func, seq);
+            }
+        }
+
+        func.block_mut(seq).instrs = rewritten;
+    }
+
+    fn access(&self, instr: &Instr) -> Option<Access> {
+        let (read, write) = (self.hooks.read, self.hooks.write);
+        let access = |hook, memory, offset, n, operands| {
+            (memory == self.hooks.memory).then_some(Access {
+                hook,
+                offset,
+                n,
+                operands,
+            })
+        };
+
+        match instr {
+            Instr::Load(load) => access(
+                read,
+                load.memory,
+                load.arg.offset,
+                load.kind.width(),
+                vec![],
+            ),
+            Instr::Store(store) => access(
+                write,
+                store.memory,
+                store.arg.offset,
+                store.kind.width(),
+                vec![store_type(store.kind)],
+            ),
+            Instr::AtomicRmw(rmw) => {
+                let ty = atomic_type(rmw.width);
+                access(
+                    write,
+                    rmw.memory,
+                    rmw.arg.offset,
+                    rmw.width.bytes(),
+                    vec![ty],
+                )
+            }
+            Instr::Cmpxchg(cmpxchg) => {
+                let ty = atomic_type(cmpxchg.width);
+                access(
+                    write,
+                    cmpxchg.memory,
+                    cmpxchg.arg.offset,
+                    cmpxchg.width.bytes(),
+                    vec![ty, ty],
+                )
+            }
+            Instr::AtomicWait(wait) => {
+                let (ty, n) = if wait.sixty_four {
+                    (ValType::I64, 8)
+                } else {
+                    (ValType::I32, 4)
+                };
+                access(
+                    read,
+                    wait.memory,
+                    wait.arg.offset,
+                    n,
+                    vec![ty, ValType::I64],
+                )
+            }
+            Instr::LoadSimd(load) => {
+                let (hook, n, operands) = simd_access(load.kind, read, write);
+                access(hook, load.memory, load.arg.offset, n, operands)
+            }
+            // `memory.atomic.notify` does not access the value at the address.
+            _ => None,
+        }
+    }
+
+    // The hook and the operand holding the address of every access of a bulk memory instruction,
+    // whose operands are [address, address or value, n]
+    fn bulk_access(&self, instr: &Instr) -> Option<Vec<(FunctionId, usize)>> {
+        let (read, write) = (self.hooks.read, self.hooks.write);
+        let main = |memory| memory == self.hooks.memory;
+
+        match instr {
+            Instr::MemoryCopy(MemoryCopy { src, dst }) => {
+                let mut accesses = Vec::with_capacity(2);
+                if main(*src) {
+                    accesses.push((read, 1));
+                }
+                if main(*dst) {
+                    accesses.push((write, 0));
+                }
+                (!accesses.is_empty()).then_some(accesses)
+            }
+            Instr::MemoryFill(MemoryFill { memory }) | Instr::MemoryInit(MemoryInit { memory, .. }) => {
+                main(*memory).then(|| vec![(write, 0)])
+            }
+            _ => None,
+        }
+    }
+
+    fn temp(&mut self, ty: ValType, position: usize) -> LocalId {
+        *self
+            .temps
+            .entry((ty, position))
+            .or_insert_with(|| self.locals.add(ty))
+    }
+
+    // Stack before: [addr, operands...], after: [addr, operands...]
+    fn emit_access(&mut self, out: &mut Vec<(Instr, InstrLocId)>, access: Access, iidx: i32) {
+        let temps: Vec<LocalId> = access
+            .operands
+            .iter()
+            .enumerate()
+            .map(|(position, ty)| self.temp(*ty, position))
+            .collect();
+        let addr = self.temp(ValType::I32, access.operands.len());
+
+        let mut instrs: Vec<Instr> = Vec::new();
+        instrs.extend(
+            temps
+                .iter()
+                .rev()
+                .map(|local| LocalSet { local: *local }.into()),
+        );
+        instrs.push(LocalTee { local: addr }.into());
+        instrs.push(LocalGet { local: addr }.into());
+        if access.offset != 0 {
+            // Offsets of 32-bit memories always fit into an i32.
+            instrs.push(i32_const(access.offset as i32));
+            instrs.push(
+                Binop {
+                    op: BinaryOp::I32Add,
+                }
+                .into(),
+            );
+        }
+        instrs.push(i32_const(access.n as i32));
+        instrs.push(i32_const(self.fidx));
+        instrs.push(i32_const(iidx));
+        instrs.push(Call { func: access.hook }.into());
+        instrs.extend(temps.iter().map(|local| LocalGet { local: *local }.into()));
+
+        out.extend(
+            instrs
+                .into_iter()
+                .map(|instr| (instr, InstrLocId::default())),
+        );
+    }
+
+    // Stack before: [a, b, n], after: [a, b, n]
+    fn emit_bulk_access(&mut self, out: &mut Vec<(Instr, InstrLocId)>, accesses: &[(FunctionId, usize)], iidx: i32) {
+        let operands: Vec<LocalId> = (0..3).map(|position| self.temp(ValType::I32, position)).collect();
+
+        let mut instrs: Vec<Instr> = Vec::new();
+        instrs.extend(operands.iter().rev().map(|local| LocalSet { local: *local }.into()));
+        for (hook, addr) in accesses {
+            instrs.push(LocalGet { local: operands[*addr] }.into());
+            instrs.push(LocalGet { local: operands[2] }.into());
+            instrs.push(i32_const(self.fidx));
+            instrs.push(i32_const(iidx));
+            instrs.push(Call { func: *hook }.into());
+        }
+        instrs.extend(operands.iter().map(|local| LocalGet { local: *local }.into()));
+
+        out.extend(
+            instrs
+                .into_iter()
+                .map(|instr| (instr, InstrLocId::default())),
+        );
+    }
+
+    // Stack before: [arg], after: [arg], or unchanged for intrinsics without an argument
+    fn emit_intrinsic(&mut self, out: &mut Vec<(Instr, InstrLocId)>, hook: FunctionId, args: usize, iidx: i32) {
+        let mut instrs: Vec<Instr> = Vec::with_capacity(5);
+        if args == 1 {
+            let arg = self.temp(ValType::I32, 0);
+            instrs.push(LocalTee { local: arg }.into());
+            instrs.push(LocalGet { local: arg }.into());
+        }
+        instrs.push(i32_const(self.fidx));
+        instrs.push(i32_const(iidx));
+        instrs.push(Call { func: hook }.into());
+        out.extend(
+            instrs
+                .into_iter()
+                .map(|instr| (instr, InstrLocId::default())),
+        );
+    }
+}
+
+fn i32_const(value: i32) -> Instr {
+    Const {
+        value: Value::I32(value),
+    }
+    .into()
+}
+
+fn store_type(kind: StoreKind) -> ValType {
+    match kind {
+        StoreKind::I32 { .. } | StoreKind::I32_8 { .. } | StoreKind::I32_16 { .. } => ValType::I32,
+        StoreKind::I64 { .. }
+        | StoreKind::I64_8 { .. }
+        | StoreKind::I64_16 { .. }
+        | StoreKind::I64_32 { .. } => ValType::I64,
+        StoreKind::F32 => ValType::F32,
+        StoreKind::F64 => ValType::F64,
+        StoreKind::V128 => ValType::V128,
+    }
+}
+
+fn atomic_type(width: walrus::ir::AtomicWidth) -> ValType {
+    use walrus::ir::AtomicWidth;
+    match width {
+        AtomicWidth::I32 | AtomicWidth::I32_8 | AtomicWidth::I32_16 => ValType::I32,
+        AtomicWidth::I64 | AtomicWidth::I64_8 | AtomicWidth::I64_16 | AtomicWidth::I64_32 => {
+            ValType::I64
+        }
+    }
+}
+
+fn simd_access(
+    kind: LoadSimdKind,
+    read: FunctionId,
+    write: FunctionId,
+) -> (FunctionId, u32, Vec<ValType>) {
+    match kind {
+        LoadSimdKind::Splat8 => (read, 1, vec![]),
+        LoadSimdKind::Splat16 => (read, 2, vec![]),
+        LoadSimdKind::Splat32 | LoadSimdKind::V128Load32Zero => (read, 4, vec![]),
+        LoadSimdKind::Splat64
+        | LoadSimdKind::V128Load64Zero
+        | LoadSimdKind::V128Load8x8S
+        | LoadSimdKind::V128Load8x8U
+        | LoadSimdKind::V128Load16x4S
+        | LoadSimdKind::V128Load16x4U
+        | LoadSimdKind::V128Load32x2S
+        | LoadSimdKind::V128Load32x2U => (read, 8, vec![]),
+        LoadSimdKind::V128Load8Lane(_) => (read, 1, vec![ValType::V128]),
+        LoadSimdKind::V128Load16Lane(_) => (read, 2, vec![ValType::V128]),
+        LoadSimdKind::V128Load32Lane(_) => (read, 4, vec![ValType::V128]),
+        LoadSimdKind::V128Load64Lane(_) => (read, 8, vec![ValType::V128]),
+        LoadSimdKind::V128Store8Lane(_) => (write, 1, vec![ValType::V128]),
+        LoadSimdKind::V128Store16Lane(_) => (write, 2, vec![ValType::V128]),
+        LoadSimdKind::V128Store32Lane(_) => (write, 4, vec![ValType::V128]),
+        LoadSimdKind::V128Store64Lane(_) => (write, 8, vec![ValType::V128]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use walrus::{ir::Instr, FunctionKind, Module};
+
+    use super::{instrument, Config, HOOK_MODULE};
+
+    fn calls_to(module: &Module, name: &str) -> usize {
+        let Some(hook) = module.funcs.by_name(name).or_else(|| {
+            module
+                .imports
+                .iter()
+                .find(|import| import.name == name)
+                .and_then(|import| match import.kind {
+                    walrus::ImportKind::Function(id) => Some(id),
+                    _ => None,
+                })
+        }) else {
+            return 0;
+        };
+
+        module
+            .funcs
+            .iter()
+            .filter_map(|func| match &func.kind {
+                FunctionKind::Local(local) => Some(local),
+                _ => None,
+            })
+            .flat_map(|local| local.block(local.entry_block()).instrs.iter())
+            .filter(|(instr, _)| matches!(instr, Instr::Call(call) if call.func == hook))
+            .count()
+    }
+
+    #[test]
+    fn test_imports_hooks() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1)
+                (func (param i32) (result i32)
+                    local.get 0
+                    i32.const 5
+                    i32.store offset=4
+                    local.get 0
+                    i32.load offset=4))"#,
+        )
+        .unwrap();
+
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+
+        assert!(module
+            .imports
+            .iter()
+            .any(|import| import.module == HOOK_MODULE && import.name == "read_event"));
+        assert_eq!(calls_to(&module, "read_event"), 1);
+        assert_eq!(calls_to(&module, "write_event"), 1);
+
+        // The store is the third and the load the fifth instruction of the function
+        let locations: Vec<_> = output.locations.iter().map(|(loc, _)| *loc).collect();
+        assert_eq!(locations, vec![(0, 2), (0, 4)]);
+    }
+
+    #[test]
+    fn test_skips_runtime() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1)
+                (func $read_event (export "read_event") (param i32 i32 i32 i32)
+                    call $enter_tracer
+                    drop
+                    local.get 0
+                    call $record
+                    call $exit_tracer)
+                (func $write_event (export "write_event") (param i32 i32 i32 i32))
+                (func $start_lock (export "start_lock") (param i32))
+                (func $request_event (export "request_event") (param i32 i32 i32))
+                (func $enter_tracer (export "enter_tracer") (result i32)
+                    call $flag
+                    i32.load8_u)
+                (func $exit_tracer (export "exit_tracer")
+                    call $flag
+                    i32.const 0
+                    i32.store8)
+                (func $flag (result i32)
+                    i32.const 16)
+                (func $record (param i32)
+                    local.get 0
+                    i32.load
+                    drop)
+                (func (param i32)
+                    local.get 0
+                    call $start_lock
+                    local.get 0
+                    i32.load
+                    drop))"#,
+        )
+        .unwrap();
+
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+
+        assert!(module.imports.iter().next().is_none());
+        // Only the guard is skipped, functions called by the hooks are instrumented
+        assert_eq!(calls_to(&module, "read_event"), 2);
+        assert_eq!(calls_to(&module, "write_event"), 0);
+        assert_eq!(calls_to(&module, "request_event"), 1);
+    }
+
+    #[test]
+    fn test_bulk_memory() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1)
+                (data $data "wasm-ca")
+                (func (param i32 i32 i32)
+                    local.get 0
+                    local.get 1
+                    local.get 2
+                    memory.copy
+                    local.get 0
+                    i32.const 0
+                    local.get 2
+                    memory.fill
+                    local.get 0
+                    i32.const 0
+                    i32.const 7
+                    memory.init $data))"#,
+        )
+        .unwrap();
+
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        wasmparser::validate(&output.module).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+
+        assert_eq!(calls_to(&module, "read_event"), 1);
+        assert_eq!(calls_to(&module, "write_event"), 3);
+        let locations: Vec<_> = output.locations.iter().map(|(loc, _)| *loc).collect();
+        assert_eq!(locations, vec![(0, 3), (0, 7), (0, 11)]);
+    }
+
+    #[test]
+    fn test_intrinsics_without_arguments() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1)
+                (func $begin_atomic (export "begin_atomic"))
+                (func $end_atomic (export "end_atomic"))
+                (func
+                    call $begin_atomic
+                    call $end_atomic))"#,
+        )
+        .unwrap();
+
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        wasmparser::validate(&output.module).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+
+        assert_eq!(calls_to(&module, "begin_event"), 1);
+        assert_eq!(calls_to(&module, "end_event"), 1);
+        let begin = module.imports.iter().find(|import| import.name == "begin_event").unwrap();
+        let walrus::ImportKind::Function(begin) = begin.kind else { panic!() };
+        assert_eq!(module.types.get(module.funcs.get(begin).ty()).params().len(), 2);
+    }
+
+    #[test]
+    fn test_thread_memory() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1 1 shared)
+                (global $__stack_pointer (mut i32) (i32.const 65536))
+                (global (export "__tls_base") (mut i32) (i32.const 0))
+                (global (export "__tls_size") i32 (i32.const 16))
+                (func $thread_memory (export "thread_memory"))
+                (func
+                    call $thread_memory))"#,
+        )
+        .unwrap();
+
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        wasmparser::validate(&output.module).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+
+        assert_eq!(calls_to(&module, "thread_memory_event"), 1);
+        // The call has no location, as it is not an event
+        assert!(output.locations.iter().next().is_none());
+
+        // Without the globals there is nothing to pass to the hook
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory 1)
+                (func $thread_memory (export "thread_memory"))
+                (func
+                    call $thread_memory))"#,
+        )
+        .unwrap();
+        let output = instrument(&wasm, &Config::default()).unwrap();
+        let module = Module::from_buffer(&output.module).unwrap();
+        assert_eq!(calls_to(&module, "thread_memory_event"), 0);
+    }
+}
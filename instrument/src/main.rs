@@ -0,0 +1,76 @@
+use std::{fs::File, io::BufWriter, process::ExitCode};
+
+use wasm_ca_instrument::{instrument, Config, Error};
+
+const USAGE: &str = "usage: wasm-ca-instrument <input.wasm> -o <output.wasm> \
+    [--locations <output.locations>] [--hook-module <name>]";
+
+struct Args {
+    input: String,
+    output: String,
+    locations: Option<String>,
+    config: Config,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut locations = None;
+    let mut config = Config::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().ok_or("missing value for -o")?),
+            "--locations" => locations = Some(args.next().ok_or("missing value for --locations")?),
+            "--hook-module" => {
+                config.hook_module = args.next().ok_or("missing value for --hook-module")?
+            }
+            "-h" | "--help" => return Err(String::from(USAGE)),
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(arg),
+            _ => return Err(format!("unexpected argument '{arg}'\n{USAGE}")),
+        }
+    }
+
+    Ok(Args {
+        input: input.ok_or(USAGE)?,
+        output: output.ok_or(USAGE)?,
+        locations,
+        config,
+    })
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let wasm = std::fs::read(&args.input)?;
+    let output = instrument(&wasm, &args.config)?;
+    std::fs::write(&args.output, output.module)?;
+
+    // The location map is written next to the module unless requested otherwise
+    let locations = args
+        .locations
+        .unwrap_or_else(|| format!("{}.locations", args.output));
+    output
+        .locations
+        .write(BufWriter::new(File::create(locations)?))
+        .map_err(|e| Error::Io(std::io::Error::other(e.to_string())))?;
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
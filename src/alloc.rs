@@ -0,0 +1,144 @@
+use std::alloc::{GlobalAlloc, Layout};
+
+use crate::tracing::{self, Op, UNKNOWN_LOCATION};
+
+/// A [`GlobalAlloc`] that records an Alloc and a Free event for every allocation of `A`.
+///
+/// Freed memory is often reused for unrelated objects. With the Free events, the analyses and
+/// the RapidBin, STD and CSV exporters give variables and locks at reused addresses a fresh
+/// identity.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: TracingAllocator<System> = TracingAllocator::new(System);
+/// ```
+pub struct TracingAllocator<A: GlobalAlloc> {
+    inner: A,
+}
+
+impl<A: GlobalAlloc> TracingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TracingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            tracing::add_event(Op::Alloc { addr: ptr as usize, size: layout.size() }, UNKNOWN_LOCATION);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            tracing::add_event(Op::Alloc { addr: ptr as usize, size: layout.size() }, UNKNOWN_LOCATION);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Recorded first, another thread may get the memory as soon as it is freed
+        tracing::add_event(Op::Free { addr: ptr as usize, size: layout.size() }, UNKNOWN_LOCATION);
+        self.inner.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.inner.realloc(ptr, layout, new_size);
+        let (addr, size) = (ptr as usize, layout.size());
+        if new.is_null() {
+            // The old allocation is still valid
+        } else if new == ptr {
+            // Resized in place, only the memory that changed hands gets a fresh identity
+            if new_size > size {
+                tracing::add_event(Op::Alloc { addr: addr + size, size: new_size - size }, UNKNOWN_LOCATION);
+            } else if new_size < size {
+                tracing::add_event(Op::Free { addr: addr + new_size, size: size - new_size }, UNKNOWN_LOCATION);
+            }
+        } else {
+            // Whether the allocation moves is only known now, so unlike in `dealloc`, another
+            // thread may already have gotten the old memory
+            tracing::add_event(Op::Free { addr, size }, UNKNOWN_LOCATION);
+            tracing::add_event(Op::Alloc { addr: new as usize, size: new_size }, UNKNOWN_LOCATION);
+        }
+        new
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use std::alloc::{GlobalAlloc, Layout, System};
+
+    use crate::tracing::{export_trace, TraceFormat};
+
+    use super::TracingAllocator;
+
+    // Reserves 64 bytes for every allocation, so that it can grow in place up to them
+    struct Reserving;
+
+    const RESERVED: usize = 64;
+
+    unsafe impl GlobalAlloc for Reserving {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            System.alloc(Layout::from_size_align_unchecked(RESERVED.max(layout.size()), layout.align()))
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, Layout::from_size_align_unchecked(RESERVED.max(layout.size()), layout.align()))
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            if new_size <= RESERVED {
+                return ptr;
+            }
+            let new = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+            std::ptr::copy_nonoverlapping(ptr, new, layout.size());
+            self.dealloc(ptr, layout);
+            new
+        }
+    }
+
+    // The Alloc and Free events recorded so far that start at one of `addrs`
+    fn alloc_events(addrs: &[usize]) -> Vec<(String, usize, usize)> {
+        let mut csv = Vec::new();
+        export_trace(TraceFormat::Csv, &mut csv).unwrap();
+        String::from_utf8(csv)
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(',').skip(1);
+                let (op, decor) = (fields.next()?, fields.next()?);
+                let (addr, size) = decor.strip_prefix('A')?.split_once('+')?;
+                let (addr, size) = (addr.parse().ok()?, size.parse().ok()?);
+                addrs.contains(&addr).then(|| (op.to_string(), addr, size))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_realloc() {
+        let allocator = TracingAllocator::new(Reserving);
+        let event = |op: &str, addr, size| (op.to_string(), addr, size);
+        unsafe {
+            let ptr = allocator.alloc(Layout::from_size_align(16, 8).unwrap());
+            let addr = ptr as usize;
+            // Grown and shrunk in place
+            assert_eq!(allocator.realloc(ptr, Layout::from_size_align(16, 8).unwrap(), 32), ptr);
+            assert_eq!(allocator.realloc(ptr, Layout::from_size_align(32, 8).unwrap(), 8), ptr);
+            assert_eq!(
+                alloc_events(&[addr, addr + 8, addr + 16]),
+                [event("alloc", addr, 16), event("alloc", addr + 16, 16), event("free", addr + 8, 24)]
+            );
+
+            let new = allocator.realloc(ptr, Layout::from_size_align(8, 8).unwrap(), 128);
+            assert_ne!(new, ptr);
+            assert_eq!(
+                alloc_events(&[addr, new as usize])[1..],
+                [event("free", addr, 8), event("alloc", new as usize, 128)]
+            );
+            allocator.dealloc(new, Layout::from_size_align(128, 8).unwrap());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Analyses that run on recorded [`Event`](crate::tracing::Event)s instead of exporting them.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+pub mod atomicity;
+pub mod contention;
+pub mod deadlock;
+pub mod fasttrack;
+pub mod hb;
+pub mod lockset;
+mod online;
+mod sync;
+mod vector_clock;
+pub mod wcp;
+pub mod witness;
+
+pub use vector_clock::VectorClock;
+
+// Forgets the state of the variables in a freed allocation, so that reused memory starts fresh
+pub(crate) fn forget_variables<V>(variables: &mut BTreeMap<(usize, usize), V>, addr: usize, size: usize) {
+    let freed: Vec<_> = variables
+        .range((addr, 0)..(addr.saturating_add(size), 0))
+        .map(|(variable, _)| *variable)
+        .collect();
+    for variable in freed {
+        variables.remove(&variable);
+    }
+}
+
+// Forgets the state of the locks in a freed allocation
+pub(crate) fn forget_locks<V>(locks: &mut HashMap<usize, V>, addr: usize, size: usize) {
+    locks.retain(|lock, _| !(addr..addr.saturating_add(size)).contains(lock));
+}
+
+/// Whether a memory access read or wrote its variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One side of a [`Race`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Access {
+    pub t: u32,
+    pub kind: AccessKind,
+    pub loc: (usize, usize),
+}
+
+/// Two conflicting accesses to the same `(addr, n)` variable that are not ordered.
+///
+/// `first` is the access that appears earlier in the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Race {
+    pub variable: (usize, usize),
+    pub first: Access,
+    pub second: Access,
+}
+
+impl Display for AccessKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "write"),
+        }
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} by thread {} at {}:{}", self.kind, self.t, self.loc.0, self.loc.1)
+    }
+}
+
+impl Display for Race {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (addr, n) = self.variable;
+        write!(f, "race on {addr:#x} ({n} bytes): {} and {}", self.first, self.second)
+    }
+}
@@ -0,0 +1,341 @@
+//! A conflict serializability checker for atomic regions in the style of Velodrome.
+//!
+//! Every outermost atomic region is a transaction, every event outside of one forms a
+//! transaction of its own. Transactions are ordered by program order, conflicting accesses,
+//! releases and acquisitions of the same lock, and fork/join. The trace is conflict serializable
+//! iff the resulting graph is acyclic, so an edge that closes a cycle reveals a region that is
+//! not atomic: another thread's transaction happens both after and before a part of it.
+//!
+//! Like Velodrome, single events that add no order are merged into their thread's previous
+//! transaction, and transactions that can no longer be part of a cycle are collected, so the
+//! graph stays small.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+use crate::tracing::{Event, Op};
+
+use super::{forget_locks, forget_variables};
+
+struct Transaction {
+    t: u32,
+    // The location of the Begin event, `None` for single events outside of a region
+    begin: Option<(usize, usize)>,
+    successors: Vec<usize>,
+    // The number of edges from transactions that are not collected yet
+    incoming: usize,
+    // Every region is reported at most once
+    reported: bool,
+}
+
+#[derive(Default)]
+struct ThreadState {
+    // The open region and its nesting depth
+    region: Option<(usize, usize)>,
+    last: Option<usize>,
+    // The transaction that forked this thread, before the thread's first transaction exists
+    forked_by: Option<usize>,
+}
+
+/// An atomic region that is not serializable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtomicityViolation {
+    pub t: u32,
+    /// Location of the Begin event of the region.
+    pub begin: (usize, usize),
+    /// Location of the event in the region that completed the cycle.
+    pub conflict: (usize, usize),
+    /// The transactions of the cycle after the region, as thread and Begin location.
+    /// Single events outside of a region have no Begin location.
+    pub cycle: Vec<(u32, Option<(usize, usize)>)>,
+}
+
+#[derive(Default)]
+pub struct Velodrome {
+    transactions: HashMap<usize, Transaction>,
+    next_id: usize,
+    threads: HashMap<u32, ThreadState>,
+    last_write: BTreeMap<(usize, usize), usize>,
+    last_reads: BTreeMap<(usize, usize), HashMap<u32, usize>>,
+    last_release: HashMap<usize, usize>,
+}
+
+impl Velodrome {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the transaction `t` executes its current event in, given the transactions the
+    /// event has to come after.
+    fn current(&mut self, t: u32, loc: (usize, usize), op: &Op, predecessors: &[usize]) -> usize {
+        let state = self.threads.entry(t).or_default();
+        if let Some((transaction, depth)) = &mut state.region {
+            match op {
+                Op::Begin => *depth += 1,
+                Op::End => *depth -= 1,
+                _ => {}
+            }
+            let transaction = *transaction;
+            if state.region.is_some_and(|(_, depth)| depth == 0) {
+                state.region = None;
+            }
+            return transaction;
+        }
+
+        // A single event that is only ordered after the thread's previous transaction cannot
+        // be part of a cycle of its own, so it is merged into that transaction
+        let begin = matches!(op, Op::Begin).then_some(loc);
+        if let Some(last) = state.last.filter(|last| begin.is_none() && predecessors.iter().all(|p| p == last)) {
+            return last;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        if begin.is_some() {
+            state.region = Some((id, 1));
+        }
+        let last = state.last.replace(id);
+        let previous = last.or(state.forked_by.take());
+        self.transactions.insert(
+            id,
+            Transaction { t, begin, successors: Vec::new(), incoming: 0, reported: false },
+        );
+        if let Some(previous) = previous {
+            self.link(previous, id);
+        }
+        if let Some(last) = last {
+            self.collect(last);
+        }
+        id
+    }
+
+    fn link(&mut self, from: usize, to: usize) {
+        if let (true, Some(to_transaction)) = (self.transactions.contains_key(&from), self.transactions.get_mut(&to)) {
+            to_transaction.incoming += 1;
+            self.transactions.get_mut(&from).unwrap().successors.push(to);
+        }
+    }
+
+    // Removes `transaction` and the transactions only it leads to, if they can no longer be part
+    // of a cycle: edges only lead into the last transaction of a thread, so a transaction that
+    // is not the last of its thread and has no incoming edges will never have any.
+    fn collect(&mut self, transaction: usize) {
+        let mut garbage = vec![transaction];
+        while let Some(transaction) = garbage.pop() {
+            let unreachable = self.transactions.get(&transaction).is_some_and(|tr| tr.incoming == 0)
+                && !self.threads.values().any(|state| state.last == Some(transaction));
+            if !unreachable {
+                continue;
+            }
+            for successor in self.transactions.remove(&transaction).unwrap().successors {
+                if let Some(successor_transaction) = self.transactions.get_mut(&successor) {
+                    successor_transaction.incoming -= 1;
+                    if successor_transaction.incoming == 0 {
+                        garbage.push(successor);
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns the path from `from` to `to`, excluding `from`, if there is one
+    fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut parents = HashMap::from([(from, from)]);
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while parents[&current] != from {
+                    current = parents[&current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for successor in &self.transactions[&node].successors {
+                if !parents.contains_key(successor) {
+                    parents.insert(*successor, node);
+                    stack.push(*successor);
+                }
+            }
+        }
+        None
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, loc: (usize, usize)) -> Option<AtomicityViolation> {
+        // `from` may have been collected when `to` was created
+        if from == to || self.transactions.get(&from).is_none_or(|from| from.successors.contains(&to)) {
+            return None;
+        }
+
+        // `to` is the current transaction, so a cycle exists if it already reaches `from`.
+        // The edge closing it is left out, so that the graph stays acyclic. Such a path needs
+        // an edge out of `to` and one into `from`, which most new edges lack.
+        let closes_cycle = !self.transactions[&to].successors.is_empty() && self.transactions[&from].incoming > 0;
+        let Some(path) = closes_cycle.then(|| self.path(to, from)).flatten() else {
+            self.link(from, to);
+            return None;
+        };
+
+        let region = self.transactions.get_mut(&to).unwrap();
+        if region.reported {
+            return None;
+        }
+        region.reported = true;
+        let (t, begin) = (region.t, region.begin?);
+
+        // Consecutive single events of the same thread are listed once
+        let mut cycle: Vec<_> = path
+            .iter()
+            .map(|i| (self.transactions[i].t, self.transactions[i].begin))
+            .collect();
+        cycle.dedup();
+        Some(AtomicityViolation { t, begin, conflict: loc, cycle })
+    }
+
+    /// Processes the next event of the trace and returns the violations it reveals.
+    pub fn process(&mut self, event: &Event) -> Vec<AtomicityViolation> {
+        let Event { t, op, loc } = *event;
+        let mut predecessors = Vec::new();
+        match op {
+            Op::Alloc { .. } => return Vec::new(),
+            Op::Free { addr, size } => {
+                forget_variables(&mut self.last_write, addr, size);
+                forget_variables(&mut self.last_reads, addr, size);
+                forget_locks(&mut self.last_release, addr, size);
+                return Vec::new();
+            }
+            Op::Read { addr, n } => predecessors.extend(self.last_write.get(&(addr, n)).copied()),
+            Op::Write { addr, n } => {
+                predecessors.extend(self.last_write.get(&(addr, n)).copied());
+                predecessors.extend(self.last_reads.get(&(addr, n)).into_iter().flat_map(|reads| reads.values().copied()));
+            }
+            Op::Aquire { lock } => predecessors.extend(self.last_release.get(&lock).copied()),
+            Op::Join { tid } => {
+                predecessors.extend(self.threads.get(&tid).and_then(|state| state.last));
+            }
+            Op::Release { .. } | Op::Fork { .. } | Op::Request { .. } | Op::Begin | Op::End => {}
+        }
+        // Collected transactions are not part of any cycle
+        predecessors.retain(|p| self.transactions.contains_key(p));
+        let current = self.current(t, loc, &op, &predecessors);
+
+        match op {
+            Op::Read { addr, n } => {
+                self.last_reads.entry((addr, n)).or_default().insert(t, current);
+            }
+            Op::Write { addr, n } => {
+                self.last_write.insert((addr, n), current);
+                self.last_reads.remove(&(addr, n));
+            }
+            Op::Release { lock } => {
+                self.last_release.insert(lock, current);
+            }
+            Op::Fork { tid } => {
+                let child = self.threads.entry(tid).or_default();
+                match child.last {
+                    Some(first) => self.link(current, first),
+                    None => child.forked_by = Some(current),
+                }
+            }
+            _ => {}
+        }
+
+        predecessors
+            .into_iter()
+            .filter_map(|from| self.add_edge(from, current, loc))
+            .collect()
+    }
+}
+
+/// Checks a complete trace for atomicity violations.
+pub fn detect<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<AtomicityViolation> {
+    let mut checker = Velodrome::new();
+    events.into_iter().flat_map(|e| checker.process(e)).collect()
+}
+
+impl Display for AtomicityViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "atomic region of thread {} at {}:{} is not serializable, conflict at {}:{} with:",
+            self.t, self.begin.0, self.begin.1, self.conflict.0, self.conflict.1
+        )?;
+        for (t, begin) in &self.cycle {
+            match begin {
+                Some((fidx, iidx)) => write!(f, "\n    atomic region of thread {t} at {fidx}:{iidx}")?,
+                None => write!(f, "\n    an event of thread {t}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::{detect, Velodrome};
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    fn locked(t: u32, lock: usize, op: Op) -> [Event; 3] {
+        [event(t, Op::Aquire { lock }), event(t, op), event(t, Op::Release { lock })]
+    }
+
+    #[test]
+    fn test_interleaved_update() {
+        // Thread 1 reads and writes the variable in separate critical sections, and thread 2
+        // writes it in between
+        let mut trace = vec![event(0, Op::Fork { tid: 1 }), event(0, Op::Fork { tid: 2 }), event(1, Op::Begin)];
+        trace.extend(locked(1, 1, Op::Read { addr: 8, n: 4 }));
+        trace.extend(locked(2, 1, Op::Write { addr: 8, n: 4 }));
+        trace.extend(locked(1, 1, Op::Write { addr: 8, n: 4 }));
+        trace.push(event(1, Op::End));
+
+        let violations = detect(&trace);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].t, 1);
+        assert_eq!(violations[0].cycle, vec![(2, None)]);
+    }
+
+    #[test]
+    fn test_serializable_regions() {
+        let mut trace = vec![event(0, Op::Fork { tid: 1 }), event(0, Op::Fork { tid: 2 })];
+        for t in [1, 2, 1] {
+            trace.push(event(t, Op::Begin));
+            trace.push(event(t, Op::Begin));
+            trace.extend(locked(t, 1, Op::Read { addr: 8, n: 4 }));
+            trace.push(event(t, Op::End));
+            trace.extend(locked(t, 1, Op::Write { addr: 8, n: 4 }));
+            trace.push(event(t, Op::End));
+        }
+        assert!(detect(&trace).is_empty());
+    }
+
+    #[test]
+    fn test_graph_stays_small() {
+        let mut checker = Velodrome::new();
+        checker.process(&event(0, Op::Fork { tid: 1 }));
+        for i in 0..100 {
+            // Unordered single events are merged
+            checker.process(&event(0, Op::Write { addr: 16 + i, n: 1 }));
+        }
+        assert_eq!(checker.transactions.len(), 1);
+
+        for _ in 0..100 {
+            for t in [0, 1] {
+                for event in locked(t, 1, Op::Write { addr: 8, n: 4 }) {
+                    assert!(checker.process(&event).is_empty());
+                }
+            }
+        }
+        // Only the transactions that can still be part of a cycle are kept
+        assert!(checker.transactions.len() <= 4, "{} transactions", checker.transactions.len());
+    }
+}
@@ -0,0 +1,281 @@
+//! Lock contention profiling from the Request, Aquire and Release events of a trace.
+//!
+//! A lock acquisition is contended if another thread held the lock when it was requested. Wait
+//! times span from the Request to the Aquire event and hold times from the Aquire to the
+//! Release event, so they are only known if the events have timestamps (see
+//! [`set_timestamps`](crate::tracing::set_timestamps)). Without them, only the counts are.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io::Write,
+};
+
+use crate::{
+    error::Error,
+    tracing::{Event, Op},
+};
+
+/// The number of waiting locations shown when a profile is displayed.
+const TOP_LOCATIONS: usize = 5;
+
+/// Hold times in power of two buckets: bucket 0 counts holds shorter than 1us, bucket `i`
+/// those from `2^(i-1)` up to `2^i` microseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub buckets: Vec<u64>,
+}
+
+impl Histogram {
+    fn add(&mut self, us: u64) {
+        let bucket = (u64::BITS - us.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for (bucket, count) in self.buckets.iter().enumerate().filter(|(_, count)| **count > 0) {
+            match bucket {
+                0 => write!(f, "{separator}<1us: {count}")?,
+                _ => write!(f, "{separator}{}-{}us: {count}", 1u64 << (bucket - 1), 1u64 << bucket)?,
+            }
+            separator = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// The waits for a lock that were requested at the same location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitingLocation {
+    pub loc: (usize, usize),
+    pub waits: u64,
+    pub total_wait_us: u64,
+}
+
+/// The contention of a single lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockProfile {
+    pub lock: usize,
+    pub acquisitions: u64,
+    pub contended: u64,
+    pub total_wait_us: u64,
+    pub max_wait_us: u64,
+    pub hold_times: Histogram,
+    /// Sorted by their total wait time, longest first.
+    pub waiting_locations: Vec<WaitingLocation>,
+}
+
+impl LockProfile {
+    fn new(lock: usize) -> Self {
+        Self {
+            lock,
+            acquisitions: 0,
+            contended: 0,
+            total_wait_us: 0,
+            max_wait_us: 0,
+            hold_times: Histogram::default(),
+            waiting_locations: Vec::new(),
+        }
+    }
+}
+
+impl Display for LockProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lock {:#x}: {} acquisitions, {} contended, waited {}us in total and {}us at most",
+            self.lock, self.acquisitions, self.contended, self.total_wait_us, self.max_wait_us
+        )?;
+        if !self.hold_times.buckets.is_empty() {
+            write!(f, "\n    hold times: {}", self.hold_times)?;
+        }
+        for location in self.waiting_locations.iter().take(TOP_LOCATIONS) {
+            write!(
+                f,
+                "\n    waited {}us in {} waits at {}:{}",
+                location.total_wait_us, location.waits, location.loc.0, location.loc.1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A pending Request: whether it is contended, its location and timestamp
+type Pending = (bool, (usize, usize), Option<u64>);
+
+/// Profiles the contention of every lock in `events`, where `timestamps` holds the timestamp
+/// of each event, if any. The locks are sorted by their total wait time, longest first.
+pub fn profile(events: &[Event], timestamps: &[Option<u64>]) -> Vec<LockProfile> {
+    let mut profiles: HashMap<usize, LockProfile> = HashMap::new();
+    let mut owners: HashMap<usize, u32> = HashMap::new();
+    let mut requests: HashMap<(u32, usize), Pending> = HashMap::new();
+    let mut acquires: HashMap<(u32, usize), Option<u64>> = HashMap::new();
+    // The number and total time of contended waits per lock and request location
+    let mut locations: HashMap<(usize, (usize, usize)), (u64, u64)> = HashMap::new();
+
+    let timestamps = timestamps.iter().copied().chain(std::iter::repeat(None));
+    for (Event { t, op, loc }, timestamp) in events.iter().zip(timestamps) {
+        match *op {
+            Op::Request { lock } => {
+                let contended = owners.get(&lock).is_some_and(|owner| owner != t);
+                requests.insert((*t, lock), (contended, *loc, timestamp));
+            }
+            Op::Aquire { lock } => {
+                let profile = profiles.entry(lock).or_insert_with(|| LockProfile::new(lock));
+                profile.acquisitions += 1;
+                if let Some((contended, request_loc, requested)) = requests.remove(&(*t, lock)) {
+                    profile.contended += u64::from(contended);
+                    if let (Some(requested), Some(acquired)) = (requested, timestamp) {
+                        let wait = acquired.saturating_sub(requested);
+                        profile.total_wait_us += wait;
+                        profile.max_wait_us = profile.max_wait_us.max(wait);
+                        if contended {
+                            let location = locations.entry((lock, request_loc)).or_default();
+                            location.0 += 1;
+                            location.1 += wait;
+                        }
+                    }
+                }
+                owners.insert(lock, *t);
+                acquires.insert((*t, lock), timestamp);
+            }
+            Op::Release { lock } => {
+                if owners.get(&lock) == Some(t) {
+                    owners.remove(&lock);
+                }
+                let acquired = acquires.remove(&(*t, lock)).flatten();
+                if let (Some(acquired), Some(released), Some(profile)) = (acquired, timestamp, profiles.get_mut(&lock)) {
+                    profile.hold_times.add(released.saturating_sub(acquired));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for ((lock, loc), (waits, total_wait_us)) in locations {
+        if let Some(profile) = profiles.get_mut(&lock) {
+            profile.waiting_locations.push(WaitingLocation { loc, waits, total_wait_us });
+        }
+    }
+    let mut profiles: Vec<_> = profiles.into_values().collect();
+    for profile in &mut profiles {
+        profile.waiting_locations.sort_by_key(|l| (std::cmp::Reverse(l.total_wait_us), l.loc));
+    }
+    profiles.sort_by_key(|p| (std::cmp::Reverse(p.total_wait_us), p.lock));
+    profiles
+}
+
+/// Writes `profiles` as JSON, with a `locks` array of objects with the fields of
+/// [`LockProfile`]. Locations are `"<fidx>:<iidx>"` strings and the hold time histogram is the
+/// array of its buckets.
+pub fn write_profiles_json<W: Write>(profiles: &[LockProfile], mut writer: W) -> Result<(), Error> {
+    write!(writer, r#"{{"locks":["#)?;
+    for (i, profile) in profiles.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let buckets: Vec<_> = profile.hold_times.buckets.iter().map(u64::to_string).collect();
+        write!(
+            writer,
+            r#"{separator}{{"lock":{},"acquisitions":{},"contended":{},"total_wait_us":{},"max_wait_us":{},"hold_times":[{}],"waiting_locations":["#,
+            profile.lock,
+            profile.acquisitions,
+            profile.contended,
+            profile.total_wait_us,
+            profile.max_wait_us,
+            buckets.join(",")
+        )?;
+        for (j, location) in profile.waiting_locations.iter().enumerate() {
+            let separator = if j == 0 { "" } else { "," };
+            write!(
+                writer,
+                r#"{separator}{{"loc":"{}:{}","waits":{},"total_wait_us":{}}}"#,
+                location.loc.0, location.loc.1, location.waits, location.total_wait_us
+            )?;
+        }
+        write!(writer, "]}}")?;
+    }
+    writeln!(writer, "]}}")?;
+    Ok(())
+}
+
+/// Returns the contention profile of every lock in the events recorded so far, one after another.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "lock_contention_report")]
+pub fn lock_contention_report() -> String {
+    let profiles = crate::tracing::profile_contention();
+    profiles.iter().map(|profile| format!("{profile}\n")).collect()
+}
+
+/// Returns the contention profile of every lock in the events recorded so far as JSON, see
+/// [`write_profiles_json`].
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "lock_contention_json")]
+pub fn lock_contention_json() -> Result<String, Error> {
+    let mut json = Vec::new();
+    write_profiles_json(&crate::tracing::profile_contention(), &mut json)?;
+    Ok(String::from_utf8_lossy(&json).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::{profile, write_profiles_json, WaitingLocation};
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_contended_lock() {
+        let events = [
+            event(0, Op::Request { lock: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(1, Op::Request { lock: 1 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Release { lock: 1 }),
+            event(0, Op::Request { lock: 2 }),
+            event(0, Op::Aquire { lock: 2 }),
+            event(0, Op::Release { lock: 2 }),
+        ];
+        let timestamps = [0, 1, 2, 5, 6, 16, 20, 20, 21].map(Some);
+
+        let profiles = profile(&events, &timestamps);
+        assert_eq!(profiles.iter().map(|p| p.lock).collect::<Vec<_>>(), vec![1, 2]);
+
+        let lock = &profiles[0];
+        assert_eq!((lock.acquisitions, lock.contended), (2, 1));
+        assert_eq!((lock.total_wait_us, lock.max_wait_us), (5, 4));
+        // Held for 4us and 10us
+        assert_eq!(lock.hold_times.buckets, vec![0, 0, 0, 1, 1]);
+        assert_eq!(
+            lock.waiting_locations,
+            vec![WaitingLocation { loc: (1, 0), waits: 1, total_wait_us: 4 }]
+        );
+        assert_eq!(lock.hold_times.to_string(), "4-8us: 1, 8-16us: 1");
+
+        let mut json = Vec::new();
+        write_profiles_json(&profiles, &mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            concat!(
+                r#"{"locks":["#,
+                r#"{"lock":1,"acquisitions":2,"contended":1,"total_wait_us":5,"max_wait_us":4,"hold_times":[0,0,0,1,1],"#,
+                r#""waiting_locations":[{"loc":"1:0","waits":1,"total_wait_us":4}]},"#,
+                r#"{"lock":2,"acquisitions":1,"contended":0,"total_wait_us":0,"max_wait_us":0,"hold_times":[0,1],"waiting_locations":[]}"#,
+                "]}\n"
+            )
+        );
+
+        // Without timestamps only the counts are known
+        let profiles = profile(&events, &[]);
+        assert_eq!((profiles[0].acquisitions, profiles[0].contended, profiles[0].total_wait_us), (2, 1, 0));
+        assert!(profiles[0].hold_times.buckets.is_empty());
+    }
+}
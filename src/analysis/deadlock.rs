@@ -0,0 +1,275 @@
+//! Potential deadlock detection on the lock-order graph.
+//!
+//! Whenever a thread requests a lock while holding others, an edge from every held lock to the
+//! requested one is added. A cycle of edges from different threads is a potential deadlock,
+//! unless it cannot happen because
+//! - two of its edges hold a common lock besides the ones in the cycle (a guard lock), or
+//! - two of its edges are ordered by fork/join, i.e. cannot run concurrently.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::tracing::{Event, Op};
+
+use super::{sync::SyncClocks, VectorClock};
+
+/// `t` requested `to` at `request_loc` while holding `from`, acquired at `held_loc`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LockOrderEdge {
+    pub from: usize,
+    pub to: usize,
+    pub t: u32,
+    pub held_loc: (usize, usize),
+    pub request_loc: (usize, usize),
+}
+
+/// A cycle in the lock-order graph. Every edge requests the lock the next one holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PotentialDeadlock {
+    pub edges: Vec<LockOrderEdge>,
+}
+
+type Location = (usize, usize);
+
+// A lock and how often its memory has been freed before, so that reused memory is a new lock
+type Node = (usize, u32);
+
+// An edge together with its nodes, guard locks and clock, used to deduplicate edges
+type EdgeKey = (LockOrderEdge, (Node, Node), Vec<Node>, Vec<(u32, u32)>);
+
+struct Edge {
+    edge: LockOrderEdge,
+    from: Node,
+    to: Node,
+    guards: BTreeSet<Node>,
+    clock: VectorClock,
+}
+
+#[derive(Default)]
+pub struct LockOrderGraph {
+    // The held locks of each thread with the location they were acquired at
+    held: HashMap<u32, Vec<(usize, Location)>>,
+    requested: HashMap<u32, usize>,
+    // Only ordered by fork and join, see the module documentation
+    clocks: SyncClocks,
+    edges: Vec<Edge>,
+    known: HashSet<EdgeKey>,
+    generations: BTreeMap<usize, u32>,
+}
+
+impl LockOrderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn node(&mut self, lock: usize) -> Node {
+        (lock, *self.generations.entry(lock).or_insert(0))
+    }
+
+    /// Adds the next event of the trace to the graph.
+    pub fn process(&mut self, event: &Event) {
+        let Event { t, op, loc } = *event;
+        match op {
+            Op::Request { lock } => {
+                self.request(t, lock, loc);
+                self.requested.insert(t, lock);
+            }
+            Op::Aquire { lock } => {
+                // Acquisitions that were not requested first still order the locks
+                if self.requested.remove(&t) != Some(lock) {
+                    self.request(t, lock, loc);
+                }
+                self.held.entry(t).or_default().push((lock, loc));
+            }
+            Op::Release { lock } => {
+                let held = self.held.entry(t).or_default();
+                if let Some(i) = held.iter().rposition(|(l, _)| *l == lock) {
+                    held.remove(i);
+                }
+            }
+            Op::Fork { tid } => self.clocks.fork(t, tid),
+            Op::Join { tid } => self.clocks.join(t, tid),
+            Op::Free { addr, size } => {
+                for generation in self.generations.range_mut(addr..addr.saturating_add(size)) {
+                    *generation.1 += 1;
+                }
+            }
+            Op::Read { .. } | Op::Write { .. } | Op::Begin | Op::End | Op::Alloc { .. } => {}
+        }
+    }
+
+    fn request(&mut self, t: u32, lock: usize, loc: Location) {
+        let held = self.held.get(&t).cloned().unwrap_or_default();
+        let clock = self.clocks.clock(t).clone();
+        let to = self.node(lock);
+        let nodes: Vec<_> = held.iter().map(|(l, _)| self.node(*l)).collect();
+        for ((from, held_loc), from_node) in held.iter().zip(&nodes) {
+            if *from == lock {
+                continue;
+            }
+            let edge = LockOrderEdge { from: *from, to: lock, t, held_loc: *held_loc, request_loc: loc };
+            let guards: BTreeSet<_> = nodes.iter().copied().filter(|n| n != from_node).collect();
+
+            let key = (edge.clone(), (*from_node, to), guards.iter().copied().collect(), clock.entries());
+            if self.known.insert(key) {
+                self.edges.push(Edge { edge, from: *from_node, to, guards, clock: clock.clone() });
+            }
+        }
+    }
+
+    /// The distinct edges of the graph.
+    pub fn edges(&self) -> impl Iterator<Item = &LockOrderEdge> {
+        self.edges.iter().map(|e| &e.edge)
+    }
+
+    fn compatible(&self, path: &[usize], next: &Edge) -> bool {
+        path.iter().map(|i| &self.edges[*i]).all(|e| {
+            e.edge.t != next.edge.t
+                && e.from != next.from
+                && e.guards.is_disjoint(&next.guards)
+                && !e.clock.leq(&next.clock)
+                && !next.clock.leq(&e.clock)
+        })
+    }
+
+    /// Finds the cycles of the graph that are not ruled out by the filters.
+    ///
+    /// Each cycle is reported once, starting at its smallest lock.
+    pub fn deadlocks(&self) -> Vec<PotentialDeadlock> {
+        let mut outgoing: HashMap<Node, Vec<usize>> = HashMap::new();
+        for (i, e) in self.edges.iter().enumerate() {
+            outgoing.entry(e.from).or_default().push(i);
+        }
+
+        let mut deadlocks = Vec::new();
+        let mut starts: Vec<_> = outgoing.keys().copied().collect();
+        starts.sort_unstable();
+        for start in starts {
+            let mut path = Vec::new();
+            self.search(start, start, &outgoing, &mut path, &mut deadlocks);
+        }
+        deadlocks
+    }
+
+    fn search(
+        &self,
+        start: Node,
+        lock: Node,
+        outgoing: &HashMap<Node, Vec<usize>>,
+        path: &mut Vec<usize>,
+        deadlocks: &mut Vec<PotentialDeadlock>,
+    ) {
+        for i in outgoing.get(&lock).into_iter().flatten() {
+            let next = &self.edges[*i];
+            // Only locks larger than the start, so every cycle is found from its smallest lock
+            if (next.to < start) || !self.compatible(path, next) {
+                continue;
+            }
+
+            path.push(*i);
+            if next.to == start {
+                deadlocks.push(PotentialDeadlock {
+                    edges: path.iter().map(|i| self.edges[*i].edge.clone()).collect(),
+                });
+            } else if !path.iter().any(|j| self.edges[*j].from == next.to) {
+                self.search(start, next.to, outgoing, path, deadlocks);
+            }
+            path.pop();
+        }
+    }
+}
+
+/// Builds the lock-order graph of a complete trace and returns its potential deadlocks.
+pub fn detect<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<PotentialDeadlock> {
+    let mut graph = LockOrderGraph::new();
+    for e in events {
+        graph.process(e);
+    }
+    graph.deadlocks()
+}
+
+impl Display for LockOrderEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "thread {} holds lock {:#x} (acquired at {}:{}) and requests lock {:#x} at {}:{}",
+            self.t, self.from, self.held_loc.0, self.held_loc.1, self.to, self.request_loc.0, self.request_loc.1
+        )
+    }
+}
+
+impl Display for PotentialDeadlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "potential deadlock between {} threads:", self.edges.len())?;
+        for edge in &self.edges {
+            write!(f, "\n    {edge}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::detect;
+
+    fn event(t: u32, op: Op, loc: usize) -> Event {
+        Event { t, op, loc: (t as usize, loc) }
+    }
+
+    fn nested(t: u32, outer: usize, inner: usize) -> Vec<Event> {
+        vec![
+            event(t, Op::Request { lock: outer }, 0),
+            event(t, Op::Aquire { lock: outer }, 1),
+            event(t, Op::Request { lock: inner }, 2),
+            event(t, Op::Aquire { lock: inner }, 3),
+            event(t, Op::Release { lock: inner }, 4),
+            event(t, Op::Release { lock: outer }, 5),
+        ]
+    }
+
+    #[test]
+    fn test_lock_order_inversion() {
+        let mut trace = vec![event(0, Op::Fork { tid: 1 }, 0), event(0, Op::Fork { tid: 2 }, 0)];
+        trace.extend(nested(1, 10, 20));
+        trace.extend(nested(2, 20, 10));
+
+        let deadlocks = detect(&trace);
+        assert_eq!(deadlocks.len(), 1);
+        let edges = &deadlocks[0].edges;
+        assert_eq!((edges[0].from, edges[0].to, edges[0].t), (10, 20, 1));
+        assert_eq!((edges[1].from, edges[1].to, edges[1].t), (20, 10, 2));
+        assert_eq!((edges[0].held_loc, edges[0].request_loc), ((1, 1), (1, 2)));
+    }
+
+    #[test]
+    fn test_guard_lock() {
+        let mut trace = vec![event(0, Op::Fork { tid: 1 }, 0), event(0, Op::Fork { tid: 2 }, 0)];
+        for (t, outer, inner) in [(1, 10, 20), (2, 20, 10)] {
+            trace.push(event(t, Op::Aquire { lock: 5 }, 0));
+            trace.extend(nested(t, outer, inner));
+            trace.push(event(t, Op::Release { lock: 5 }, 0));
+        }
+        assert!(detect(&trace).is_empty());
+    }
+
+    #[test]
+    fn test_reused_lock() {
+        let mut trace = vec![event(0, Op::Fork { tid: 1 }, 0), event(0, Op::Fork { tid: 2 }, 0)];
+        trace.extend(nested(1, 10, 20));
+        trace.push(event(1, Op::Free { addr: 8, size: 8 }, 6));
+        trace.extend(nested(2, 20, 10));
+        assert!(detect(&trace).is_empty());
+    }
+
+    #[test]
+    fn test_fork_join_ordered() {
+        let mut trace = nested(0, 10, 20);
+        trace.push(event(0, Op::Fork { tid: 1 }, 0));
+        trace.extend(nested(1, 20, 10));
+        assert!(detect(&trace).is_empty());
+    }
+}
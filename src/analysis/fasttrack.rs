@@ -0,0 +1,284 @@
+//! An online data race detector based on FastTrack.
+//!
+//! Instead of full vector clocks, the last write of a variable is stored as an epoch
+//! `(thread, time)`, and so are its reads until they happen concurrently. The shadow state
+//! therefore grows with the number of variables and threads, not with the length of the trace.
+//! Together with [`set_recording`](crate::tracing::set_recording) this allows running the
+//! detector on sessions too long to keep the whole trace.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{console_log, tracing::{Event, Op}};
+
+use super::{forget_variables, online::Online, sync::SyncClocks, Access, AccessKind, Race, VectorClock};
+
+/// The maximum number of detected races kept until they are consumed. Older ones are dropped.
+const MAX_PENDING_RACES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Epoch {
+    t: u32,
+    time: u32,
+    loc: (usize, usize),
+}
+
+impl Epoch {
+    fn leq(&self, clock: &VectorClock) -> bool {
+        self.time <= clock.get(self.t)
+    }
+}
+
+enum ReadState {
+    None,
+    Epoch(Epoch),
+    Shared(HashMap<u32, Epoch>),
+}
+
+struct VariableState {
+    write: Option<Epoch>,
+    read: ReadState,
+}
+
+#[derive(Default)]
+pub struct FastTrack {
+    sync: SyncClocks,
+    variables: BTreeMap<(usize, usize), VariableState>,
+}
+
+fn race(variable: (usize, usize), first: &Epoch, kind: AccessKind, second: Access) -> Race {
+    Race {
+        variable,
+        first: Access { t: first.t, kind, loc: first.loc },
+        second,
+    }
+}
+
+impl FastTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes the next event of the trace and returns the races it completes.
+    pub fn process(&mut self, event: &Event) -> Vec<Race> {
+        let Event { t, op, loc } = *event;
+        self.sync.process(event);
+        match op {
+            Op::Read { addr, n } => self.read(t, (addr, n), loc),
+            Op::Write { addr, n } => self.write(t, (addr, n), loc),
+            Op::Free { addr, size } => {
+                forget_variables(&mut self.variables, addr, size);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn read(&mut self, t: u32, variable: (usize, usize), loc: (usize, usize)) -> Vec<Race> {
+        let clock = self.sync.clock(t).clone();
+        let epoch = Epoch { t, time: clock.get(t), loc };
+        let access = Access { t, kind: AccessKind::Read, loc };
+        let state = self.variables.entry(variable).or_insert(VariableState {
+            write: None,
+            read: ReadState::None,
+        });
+
+        // Same epoch: nothing new to learn
+        if let ReadState::Epoch(last) = &state.read {
+            if last.t == t && last.time == epoch.time {
+                return Vec::new();
+            }
+        }
+
+        let mut races = Vec::new();
+        if let Some(write) = state.write.filter(|w| !w.leq(&clock)) {
+            races.push(race(variable, &write, AccessKind::Write, access));
+        }
+
+        state.read = match std::mem::replace(&mut state.read, ReadState::None) {
+            ReadState::None => ReadState::Epoch(epoch),
+            ReadState::Epoch(last) if last.leq(&clock) => ReadState::Epoch(epoch),
+            ReadState::Epoch(last) => ReadState::Shared(HashMap::from([(last.t, last), (t, epoch)])),
+            ReadState::Shared(mut reads) => {
+                reads.insert(t, epoch);
+                ReadState::Shared(reads)
+            }
+        };
+
+        races
+    }
+
+    fn write(&mut self, t: u32, variable: (usize, usize), loc: (usize, usize)) -> Vec<Race> {
+        let clock = self.sync.clock(t).clone();
+        let epoch = Epoch { t, time: clock.get(t), loc };
+        let access = Access { t, kind: AccessKind::Write, loc };
+        let state = self.variables.entry(variable).or_insert(VariableState {
+            write: None,
+            read: ReadState::None,
+        });
+
+        if state.write.is_some_and(|w| w.t == t && w.time == epoch.time) {
+            state.write = Some(epoch);
+            return Vec::new();
+        }
+
+        let mut races = Vec::new();
+        if let Some(write) = state.write.filter(|w| !w.leq(&clock)) {
+            races.push(race(variable, &write, AccessKind::Write, access));
+        }
+        match &state.read {
+            ReadState::None => {}
+            ReadState::Epoch(read) => {
+                if !read.leq(&clock) {
+                    races.push(race(variable, read, AccessKind::Read, access));
+                }
+            }
+            ReadState::Shared(reads) => {
+                races.extend(
+                    reads
+                        .values()
+                        .filter(|read| !read.leq(&clock))
+                        .map(|read| race(variable, read, AccessKind::Read, access)),
+                );
+            }
+        }
+
+        // The write is ordered after all earlier reads, or reported against them, so they can be forgotten
+        state.read = ReadState::None;
+        state.write = Some(epoch);
+
+        races
+    }
+}
+
+struct State {
+    detector: FastTrack,
+    pending: VecDeque<Race>,
+    dropped: u64,
+}
+
+static ONLINE: Online<State> = Online::new();
+
+/// Runs the detector on every event as it is recorded.
+///
+/// Races are logged as soon as they are detected and queued until they are consumed through
+/// [`races`]. At most 1024 races are queued, older ones are dropped.
+pub fn enable_online() {
+    ONLINE.enable(|| State {
+        detector: FastTrack::new(),
+        pending: VecDeque::new(),
+        dropped: 0,
+    });
+}
+
+/// Stops the online detector and discards its state.
+pub fn disable_online() {
+    ONLINE.disable();
+}
+
+/// The number of races dropped because they were not consumed in time.
+pub fn dropped_races() -> u64 {
+    ONLINE.with(|state| state.dropped).unwrap_or(0)
+}
+
+/// An iterator that consumes the races detected online so far.
+pub struct Races;
+
+impl Iterator for Races {
+    type Item = Race;
+
+    fn next(&mut self) -> Option<Race> {
+        ONLINE.with(|state| state.pending.pop_front()).flatten()
+    }
+}
+
+/// Returns an iterator over the pending races, removing them from the queue.
+pub fn races() -> Races {
+    Races
+}
+
+pub(crate) fn observe(event: &Event) {
+    ONLINE.with(|state| {
+        for race in state.detector.process(event) {
+            console_log!("Data race detected: {race}");
+            if state.pending.len() == MAX_PENDING_RACES {
+                state.pending.pop_front();
+                state.dropped += 1;
+            }
+            state.pending.push_back(race);
+        }
+    });
+}
+
+/// Enables the online detector. Unless `record_trace` is set, events are no longer recorded.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "enable_race_detection")]
+pub fn enable_online_js(record_trace: bool) {
+    crate::tracing::set_recording(record_trace);
+    enable_online();
+}
+
+/// Calls `callback` with the description of every pending race and returns their number.
+///
+/// JavaScript functions cannot be shared with the workers the races are detected on,
+/// so this is meant to be polled, e.g. from `setInterval`.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn poll_races(callback: &js_sys::Function) -> Result<u32, crate::error::Error> {
+    let mut count = 0;
+    for race in races() {
+        callback.call1(&wasm_bindgen::JsValue::null(), &wasm_bindgen::JsValue::from_str(&race.to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{analysis::{hb, AccessKind}, tracing::{Event, Op}};
+
+    use super::FastTrack;
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    fn detect(trace: &[Event]) -> Vec<crate::analysis::Race> {
+        let mut detector = FastTrack::new();
+        trace.iter().flat_map(|e| detector.process(e)).collect()
+    }
+
+    #[test]
+    fn test_shared_reads() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Fork { tid: 2 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+            event(2, Op::Read { addr: 8, n: 4 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+        ];
+        let races = detect(&trace);
+        assert_eq!(races.len(), 2);
+        assert!(races.iter().all(|r| r.first.kind == AccessKind::Read && r.second.t == 0));
+    }
+
+    #[test]
+    fn test_agrees_with_hb() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Read { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(1, Op::Read { addr: 16, n: 4 }),
+            event(0, Op::Join { tid: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+        ];
+        let races = detect(&trace);
+        assert_eq!(races, hb::detect(&trace));
+        assert_eq!(races.len(), 1);
+        assert_eq!(races[0].variable, (16, 4));
+    }
+}
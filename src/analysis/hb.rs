@@ -0,0 +1,160 @@
+//! A happens-before data race detector based on vector clocks (Djit+).
+//!
+//! Happens-before is derived from program order, Release to Aquire on the same lock
+//! and Fork/Join. Request events do not order anything and are ignored.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{console_log, tracing::{Event, Op, TracerGuard}};
+
+use super::{forget_variables, online::Online, sync::SyncClocks, Access, AccessKind, Race};
+
+/// The last read and write of a variable by each thread, with the time of the access.
+#[derive(Default)]
+struct VariableState {
+    reads: HashMap<u32, (u32, Access)>,
+    writes: HashMap<u32, (u32, Access)>,
+}
+
+#[derive(Default)]
+pub struct HbDetector {
+    sync: SyncClocks,
+    variables: BTreeMap<(usize, usize), VariableState>,
+}
+
+impl HbDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes the next event of the trace and returns the races it completes.
+    pub fn process(&mut self, event: &Event) -> Vec<Race> {
+        let Event { t, op, loc } = *event;
+        self.sync.process(event);
+        match op {
+            Op::Read { addr, n } => self.access(t, (addr, n), AccessKind::Read, loc),
+            Op::Write { addr, n } => self.access(t, (addr, n), AccessKind::Write, loc),
+            Op::Free { addr, size } => {
+                forget_variables(&mut self.variables, addr, size);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn access(&mut self, t: u32, variable: (usize, usize), kind: AccessKind, loc: (usize, usize)) -> Vec<Race> {
+        let clock = self.sync.clock(t).clone();
+        let state = self.variables.entry(variable).or_default();
+        let access = Access { t, kind, loc };
+
+        // A read only conflicts with writes, a write with both
+        let previous = match kind {
+            AccessKind::Read => vec![&state.writes],
+            AccessKind::Write => vec![&state.writes, &state.reads],
+        };
+        let races = previous
+            .into_iter()
+            .flat_map(|accesses| accesses.iter())
+            .filter(|(u, (time, _))| **u != t && *time > clock.get(**u))
+            .map(|(_, (_, first))| Race { variable, first: *first, second: access })
+            .collect();
+
+        let accesses = match kind {
+            AccessKind::Read => &mut state.reads,
+            AccessKind::Write => &mut state.writes,
+        };
+        accesses.insert(t, (clock.get(t), access));
+
+        races
+    }
+}
+
+/// Runs the detector over a complete trace, e.g. one decoded with
+/// [`read_binary_trace`](crate::tracing::read_binary_trace).
+pub fn detect<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<Race> {
+    let mut detector = HbDetector::new();
+    events.into_iter().flat_map(|e| detector.process(e)).collect()
+}
+
+static ONLINE: Online<(HbDetector, Vec<Race>)> = Online::new();
+
+/// Runs the detector on every event as it is recorded. Races are logged and collected.
+pub fn enable_online() {
+    ONLINE.enable(|| (HbDetector::new(), Vec::new()));
+}
+
+/// Stops the online detector and returns the races it found.
+pub fn disable_online() -> Vec<Race> {
+    ONLINE.disable().map(|(_, races)| races).unwrap_or_default()
+}
+
+/// Returns the races found by the online detector so far.
+pub fn online_races() -> Vec<Race> {
+    let _guard = TracerGuard::enter();
+    ONLINE.with(|(_, races)| races.clone()).unwrap_or_default()
+}
+
+pub(crate) fn observe(event: &Event) {
+    ONLINE.with(|(detector, races)| {
+        for race in detector.process(event) {
+            console_log!("Data race detected: {race}");
+            races.push(race);
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{analysis::AccessKind, tracing::{Event, Op}};
+
+    use super::detect;
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_unordered_writes() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+            event(1, Op::Write { addr: 16, n: 4 }),
+        ];
+        let races = detect(&trace);
+        assert_eq!(races.len(), 1);
+        assert_eq!(races[0].variable, (8, 4));
+        assert_eq!((races[0].first.t, races[0].first.kind), (0, AccessKind::Write));
+        assert_eq!((races[0].second.t, races[0].second.kind), (1, AccessKind::Read));
+    }
+
+    #[test]
+    fn test_ordered_by_lock_and_join() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Join { tid: 1 }),
+            event(0, Op::Read { addr: 16, n: 4 }),
+        ];
+        assert!(detect(&trace).is_empty());
+    }
+
+    #[test]
+    fn test_reused_memory() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(1, Op::Alloc { addr: 8, size: 8 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Free { addr: 8, size: 8 }),
+            event(0, Op::Alloc { addr: 8, size: 4 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+        ];
+        assert!(detect(&trace).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! An Eraser style lockset analysis.
+//!
+//! Every variable starts out virgin and becomes exclusive to the first thread accessing it.
+//! Once a second thread accesses it, the candidate lockset is initialized with the locks held
+//! by that thread and intersected with the held locks on every following access. A variable
+//! only read after becoming shared is never reported; once it has been written while shared,
+//! an empty candidate lockset means it is not consistently protected by any lock.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+};
+
+use crate::tracing::{Event, Op};
+
+use super::{forget_variables, Access, AccessKind};
+
+enum State {
+    Virgin,
+    Exclusive(u32),
+    Shared,
+    SharedModified,
+}
+
+struct VariableState {
+    state: State,
+    lockset: BTreeSet<usize>,
+    // The access that made the variable shared and every access that shrank the lockset
+    history: Vec<Access>,
+    reported: bool,
+}
+
+/// A variable whose candidate lockset became empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocksetViolation {
+    pub variable: (usize, usize),
+    /// The access that shared the variable between threads, followed by the accesses that
+    /// removed locks from its candidate lockset. The last one emptied it.
+    pub accesses: Vec<Access>,
+}
+
+#[derive(Default)]
+pub struct Lockset {
+    held: HashMap<u32, BTreeSet<usize>>,
+    variables: BTreeMap<(usize, usize), VariableState>,
+}
+
+impl Lockset {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes the next event of the trace and returns the violation it reveals, if any.
+    ///
+    /// Every variable is reported at most once.
+    pub fn process(&mut self, event: &Event) -> Option<LocksetViolation> {
+        let Event { t, op, loc } = *event;
+        match op {
+            Op::Read { addr, n } => self.access(Access { t, kind: AccessKind::Read, loc }, (addr, n)),
+            Op::Write { addr, n } => self.access(Access { t, kind: AccessKind::Write, loc }, (addr, n)),
+            Op::Aquire { lock } => {
+                self.held.entry(t).or_default().insert(lock);
+                None
+            }
+            Op::Release { lock } => {
+                self.held.entry(t).or_default().remove(&lock);
+                None
+            }
+            Op::Free { addr, size } => {
+                forget_variables(&mut self.variables, addr, size);
+                None
+            }
+            Op::Request { .. } | Op::Fork { .. } | Op::Join { .. } | Op::Begin | Op::End | Op::Alloc { .. } => None,
+        }
+    }
+
+    fn access(&mut self, access: Access, variable: (usize, usize)) -> Option<LocksetViolation> {
+        let held = self.held.get(&access.t).cloned().unwrap_or_default();
+        let state = self.variables.entry(variable).or_insert(VariableState {
+            state: State::Virgin,
+            lockset: BTreeSet::new(),
+            history: Vec::new(),
+            reported: false,
+        });
+
+        let write = access.kind == AccessKind::Write;
+        match state.state {
+            State::Virgin => {
+                state.state = State::Exclusive(access.t);
+                return None;
+            }
+            State::Exclusive(t) if t == access.t => return None,
+            State::Exclusive(_) => {
+                state.state = if write { State::SharedModified } else { State::Shared };
+                state.lockset = held;
+                state.history.push(access);
+            }
+            State::Shared | State::SharedModified => {
+                if write {
+                    state.state = State::SharedModified;
+                }
+                let before = state.lockset.len();
+                state.lockset.retain(|lock| held.contains(lock));
+                if state.lockset.len() < before {
+                    state.history.push(access);
+                }
+            }
+        }
+
+        if matches!(state.state, State::SharedModified) && state.lockset.is_empty() && !state.reported {
+            state.reported = true;
+            return Some(LocksetViolation { variable, accesses: state.history.clone() });
+        }
+        None
+    }
+}
+
+/// Runs the analysis over a complete trace.
+pub fn detect<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<LocksetViolation> {
+    let mut analysis = Lockset::new();
+    events.into_iter().filter_map(|e| analysis.process(e)).collect()
+}
+
+impl Display for LocksetViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (addr, n) = self.variable;
+        write!(f, "no common lock protects {addr:#x} ({n} bytes):")?;
+        for access in &self.accesses {
+            write!(f, "\n    {access}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::detect;
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_inconsistent_locks() {
+        let trace = [
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Aquire { lock: 2 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Release { lock: 2 }),
+            event(1, Op::Release { lock: 1 }),
+            event(2, Op::Aquire { lock: 2 }),
+            event(2, Op::Write { addr: 8, n: 4 }),
+            event(2, Op::Release { lock: 2 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+        ];
+        let violations = detect(&trace);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].variable, (8, 4));
+        let threads: Vec<_> = violations[0].accesses.iter().map(|a| a.t).collect();
+        assert_eq!(threads, [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_consistent_lock_and_shared_reads() {
+        let trace = [
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+            event(2, Op::Read { addr: 8, n: 4 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Write { addr: 16, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(2, Op::Aquire { lock: 1 }),
+            event(2, Op::Write { addr: 16, n: 4 }),
+            event(2, Op::Release { lock: 1 }),
+        ];
+        assert!(detect(&trace).is_empty());
+    }
+}
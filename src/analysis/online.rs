@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+
+use crate::tracing::TracerGuard;
+
+/// The state of a detector that runs on every event as it is recorded.
+///
+/// While the detector is disabled, observing an event takes no lock.
+pub(crate) struct Online<S> {
+    enabled: AtomicBool,
+    state: Mutex<Option<S>>,
+}
+
+impl<S> Online<S> {
+    pub(crate) const fn new() -> Self {
+        Self { enabled: AtomicBool::new(false), state: Mutex::new(None) }
+    }
+
+    /// Enables the detector with the state `init` returns, unless it is already enabled.
+    pub(crate) fn enable(&self, init: impl FnOnce() -> S) {
+        // Allocating or freeing while the state is locked must not call back into the tracer
+        let _guard = TracerGuard::enter();
+        let mut state = self.state.lock();
+        if state.is_none() {
+            *state = Some(init());
+        }
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Disables the detector and returns its state.
+    pub(crate) fn disable(&self) -> Option<S> {
+        let _guard = TracerGuard::enter();
+        self.enabled.store(false, Ordering::Release);
+        self.state.lock().take()
+    }
+
+    /// Calls `f` with the state, if the detector is enabled.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut S) -> R) -> Option<R> {
+        if !self.enabled.load(Ordering::Acquire) {
+            return None;
+        }
+        self.state.lock().as_mut().map(f)
+    }
+}
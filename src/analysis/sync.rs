@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use crate::tracing::{Event, Op};
+
+use super::{forget_locks, VectorClock};
+
+/// The happens-before clocks of threads and locks, derived from program order, Release to
+/// Aquire on the same lock and Fork/Join.
+#[derive(Default)]
+pub(crate) struct SyncClocks {
+    threads: HashMap<u32, VectorClock>,
+    locks: HashMap<usize, VectorClock>,
+}
+
+impl SyncClocks {
+    /// The clock of the current event of `t`.
+    pub(crate) fn clock(&mut self, t: u32) -> &mut VectorClock {
+        self.threads.entry(t).or_insert_with(|| {
+            let mut clock = VectorClock::new();
+            clock.set(t, 1);
+            clock
+        })
+    }
+
+    pub(crate) fn acquire(&mut self, t: u32, lock: usize) {
+        if let Some(lock) = self.locks.get(&lock).cloned() {
+            self.clock(t).join(&lock);
+        }
+    }
+
+    pub(crate) fn release(&mut self, t: u32, lock: usize) {
+        let clock = self.clock(t).clone();
+        self.locks.insert(lock, clock);
+        self.clock(t).increment(t);
+    }
+
+    pub(crate) fn fork(&mut self, t: u32, tid: u32) {
+        let clock = self.clock(t).clone();
+        self.clock(tid).join(&clock);
+        self.clock(t).increment(t);
+    }
+
+    pub(crate) fn join(&mut self, t: u32, tid: u32) {
+        let clock = self.clock(tid).clone();
+        self.clock(t).join(&clock);
+        self.clock(tid).increment(tid);
+    }
+
+    /// Forgets the locks in a freed allocation.
+    pub(crate) fn free(&mut self, addr: usize, size: usize) {
+        forget_locks(&mut self.locks, addr, size);
+    }
+
+    /// Applies the synchronization of `event`, all other events are ignored.
+    pub(crate) fn process(&mut self, event: &Event) {
+        let Event { t, op, .. } = *event;
+        match op {
+            Op::Aquire { lock } => self.acquire(t, lock),
+            Op::Release { lock } => self.release(t, lock),
+            Op::Fork { tid } => self.fork(t, tid),
+            Op::Join { tid } => self.join(t, tid),
+            Op::Free { addr, size } => self.free(addr, size),
+            Op::Read { .. } | Op::Write { .. } | Op::Request { .. } | Op::Begin | Op::End | Op::Alloc { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+/// A vector clock over thread IDs. Threads that are not present have the time 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VectorClock {
+    clocks: HashMap<u32, u32>,
+}
+
+impl VectorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, t: u32) -> u32 {
+        self.clocks.get(&t).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, t: u32, time: u32) {
+        self.clocks.insert(t, time);
+    }
+
+    pub fn increment(&mut self, t: u32) {
+        *self.clocks.entry(t).or_insert(0) += 1;
+    }
+
+    /// Sets every entry to the maximum of both clocks.
+    pub fn join(&mut self, other: &VectorClock) {
+        for (t, time) in &other.clocks {
+            let entry = self.clocks.entry(*t).or_insert(0);
+            *entry = (*entry).max(*time);
+        }
+    }
+
+    /// The non-zero entries of the clock, sorted by thread.
+    pub fn entries(&self) -> Vec<(u32, u32)> {
+        let mut entries: Vec<_> = self.clocks.iter().map(|(t, time)| (*t, *time)).collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Whether every entry of `self` is at most the entry of `other`.
+    pub fn leq(&self, other: &VectorClock) -> bool {
+        self.clocks.iter().all(|(t, time)| *time <= other.get(*t))
+    }
+}
@@ -0,0 +1,284 @@
+//! A predictive data race detector based on Weak-Causal Precedence (Kini, Mathur, Viswanathan, PLDI 2017).
+//!
+//! WCP is weaker than happens-before: critical sections on the same lock are only ordered if
+//! they contain conflicting accesses (rule a), or if their acquisitions are already ordered (rule b).
+//! Races between accesses that are unordered under WCP can be exposed by a correct reordering of
+//! the trace, so it finds races from a single run that happens-before would miss, without false
+//! positives for the first race reported.
+//!
+//! Request events mark the point a thread starts waiting for a lock. Since no event of the
+//! thread can occur between the Request and its Aquire, they carry the same WCP time and the
+//! Aquire is used for the ordering. A Request without an Aquire (a thread blocked at the end of
+//! the trace) does not order anything.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::tracing::{Event, Op};
+
+use super::{forget_locks, forget_variables, sync::SyncClocks, Access, AccessKind, Race, VectorClock};
+
+struct CriticalSection {
+    t: u32,
+    // The WCP time of the acquisition
+    acquire: VectorClock,
+    // The HB time of the release, once released
+    release: Option<VectorClock>,
+}
+
+struct HeldLock {
+    lock: usize,
+    reads: HashSet<(usize, usize)>,
+    writes: HashSet<(usize, usize)>,
+}
+
+#[derive(Default)]
+struct ThreadState {
+    // WCP clock, without the own entry, which is the local time of the thread's HB clock
+    wcp: VectorClock,
+    held: Vec<HeldLock>,
+}
+
+#[derive(Default)]
+struct VariableState {
+    reads: HashMap<u32, (u32, Access)>,
+    writes: HashMap<u32, (u32, Access)>,
+}
+
+#[derive(Default)]
+pub struct Wcp {
+    hb: SyncClocks,
+    threads: HashMap<u32, ThreadState>,
+    lock_wcp: HashMap<usize, VectorClock>,
+    // HB times of the releases of critical sections that read or wrote a variable
+    last_read_release: HashMap<(usize, (usize, usize)), VectorClock>,
+    last_write_release: HashMap<(usize, (usize, usize)), VectorClock>,
+    // All critical sections of a lock, and how far each thread has processed them for rule (b)
+    sections: HashMap<usize, Vec<CriticalSection>>,
+    cursors: HashMap<(usize, u32), usize>,
+    variables: BTreeMap<(usize, usize), VariableState>,
+}
+
+impl Wcp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn thread(&mut self, t: u32) -> &mut ThreadState {
+        self.threads.entry(t).or_default()
+    }
+
+    /// The WCP time of the current event of `t`.
+    fn clock(&mut self, t: u32) -> VectorClock {
+        let mut clock = self.thread(t).wcp.clone();
+        clock.set(t, self.hb.clock(t).get(t));
+        clock
+    }
+
+    /// Processes the next event of the trace and returns the races it completes.
+    pub fn process(&mut self, event: &Event) -> Vec<Race> {
+        let Event { t, op, loc } = *event;
+        match op {
+            Op::Read { addr, n } => return self.access(t, (addr, n), AccessKind::Read, loc),
+            Op::Write { addr, n } => return self.access(t, (addr, n), AccessKind::Write, loc),
+            Op::Aquire { lock } => self.acquire(t, lock),
+            Op::Release { lock } => self.release(t, lock),
+            Op::Fork { tid } => {
+                let clock = self.clock(t);
+                self.thread(tid).wcp.join(&clock);
+                self.hb.fork(t, tid);
+            }
+            Op::Join { tid } => {
+                let clock = self.clock(tid);
+                self.thread(t).wcp.join(&clock);
+                self.hb.join(t, tid);
+            }
+            Op::Free { addr, size } => self.free(addr, size),
+            Op::Request { .. } | Op::Begin | Op::End | Op::Alloc { .. } => {}
+        }
+        Vec::new()
+    }
+
+    fn free(&mut self, addr: usize, size: usize) {
+        let freed = addr..addr.saturating_add(size);
+        forget_variables(&mut self.variables, addr, size);
+        self.hb.free(addr, size);
+        forget_locks(&mut self.lock_wcp, addr, size);
+        forget_locks(&mut self.sections, addr, size);
+        self.cursors.retain(|(lock, _), _| !freed.contains(lock));
+        for releases in [&mut self.last_read_release, &mut self.last_write_release] {
+            releases.retain(|(lock, (variable, _)), _| !freed.contains(lock) && !freed.contains(variable));
+        }
+    }
+
+    fn acquire(&mut self, t: u32, lock: usize) {
+        self.hb.acquire(t, lock);
+        let lock_wcp = self.lock_wcp.get(&lock).cloned().unwrap_or_default();
+        let state = self.thread(t);
+        state.wcp.join(&lock_wcp);
+        state.held.push(HeldLock { lock, reads: HashSet::new(), writes: HashSet::new() });
+
+        let acquire = self.clock(t);
+        self.sections.entry(lock).or_default().push(CriticalSection { t, acquire, release: None });
+    }
+
+    fn release(&mut self, t: u32, lock: usize) {
+        // Rule (b): a critical section whose acquisition is ordered before this release
+        // has its release ordered before this release as well
+        let clock = self.clock(t);
+        let sections = self.sections.entry(lock).or_default();
+        let cursor = self.cursors.entry((lock, t)).or_insert(0);
+        let mut ordered = VectorClock::new();
+        while let Some(section) = sections.get(*cursor) {
+            if section.t != t {
+                match &section.release {
+                    Some(release) if section.acquire.leq(&clock) => ordered.join(release),
+                    _ => break,
+                }
+            }
+            *cursor += 1;
+        }
+        self.thread(t).wcp.join(&ordered);
+
+        let state = self.thread(t);
+        let Some(i) = state.held.iter().rposition(|h| h.lock == lock) else { return };
+        let held = state.held.remove(i);
+        let wcp = state.wcp.clone();
+        let hb = self.hb.clock(t).clone();
+
+        for variable in held.reads {
+            self.last_read_release.entry((lock, variable)).or_default().join(&hb);
+        }
+        for variable in held.writes {
+            self.last_write_release.entry((lock, variable)).or_default().join(&hb);
+        }
+        self.lock_wcp.insert(lock, wcp);
+        if let Some(section) = self.sections.get_mut(&lock).and_then(|s| s.iter_mut().rev().find(|s| s.t == t)) {
+            section.release = Some(hb);
+        }
+
+        self.hb.release(t, lock);
+    }
+
+    fn access(&mut self, t: u32, variable: (usize, usize), kind: AccessKind, loc: (usize, usize)) -> Vec<Race> {
+        // Rule (a): conflicting critical sections on a held lock are ordered before this access
+        let held: Vec<_> = self.thread(t).held.iter().map(|h| h.lock).collect();
+        let mut ordered = VectorClock::new();
+        for lock in held {
+            if let Some(release) = self.last_write_release.get(&(lock, variable)) {
+                ordered.join(release);
+            }
+            if kind == AccessKind::Write {
+                if let Some(release) = self.last_read_release.get(&(lock, variable)) {
+                    ordered.join(release);
+                }
+            }
+        }
+
+        let state = self.thread(t);
+        state.wcp.join(&ordered);
+        for held in &mut state.held {
+            match kind {
+                AccessKind::Read => held.reads.insert(variable),
+                AccessKind::Write => held.writes.insert(variable),
+            };
+        }
+
+        let clock = self.clock(t);
+        let state = self.variables.entry(variable).or_default();
+        let access = Access { t, kind, loc };
+
+        let previous = match kind {
+            AccessKind::Read => vec![&state.writes],
+            AccessKind::Write => vec![&state.writes, &state.reads],
+        };
+        let races = previous
+            .into_iter()
+            .flat_map(|accesses| accesses.iter())
+            .filter(|(u, (time, _))| **u != t && *time > clock.get(**u))
+            .map(|(_, (_, first))| Race { variable, first: *first, second: access })
+            .collect();
+
+        let accesses = match kind {
+            AccessKind::Read => &mut state.reads,
+            AccessKind::Write => &mut state.writes,
+        };
+        accesses.insert(t, (clock.get(t), access));
+
+        races
+    }
+}
+
+/// Runs the detector over a complete trace.
+pub fn detect<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<Race> {
+    let mut detector = Wcp::new();
+    events.into_iter().flat_map(|e| detector.process(e)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{analysis::hb, tracing::{Event, Op}};
+
+    use super::detect;
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_predicts_race_hidden_by_lock() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Request { lock: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Read { addr: 24, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+        ];
+        assert!(hb::detect(&trace).is_empty());
+        let races = detect(&trace);
+        assert_eq!(races.len(), 1);
+        assert_eq!(races[0].variable, (8, 4));
+    }
+
+    #[test]
+    fn test_conflicting_critical_sections() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Read { addr: 16, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+        ];
+        assert!(detect(&trace).is_empty());
+    }
+
+    #[test]
+    fn test_ordered_acquisitions() {
+        // The critical sections do not conflict, but the second acquisition is ordered after
+        // the first one through lock 2, so its release is ordered after the first release
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Aquire { lock: 2 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Release { lock: 2 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Aquire { lock: 2 }),
+            event(1, Op::Read { addr: 16, n: 4 }),
+            event(1, Op::Release { lock: 2 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Release { lock: 1 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+        ];
+        assert!(detect(&trace).is_empty());
+    }
+}
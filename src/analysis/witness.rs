@@ -0,0 +1,269 @@
+//! Reconstructs a reordering of a trace that exhibits a reported race.
+//!
+//! The witness consists of the events the racing accesses depend on, followed by the two
+//! accesses next to each other. An event depends on its program order predecessor, the Fork of
+//! its thread, the events of a joined thread, and for reads the write it read from. Whenever two
+//! critical sections of the same lock end up in the witness, the release of the first one is
+//! needed as well. The dependencies keep their order from the trace, so every read still sees
+//! the same value and the witness is a feasible execution of the program. Only the racing
+//! accesses themselves may observe a different value.
+//!
+//! Races that can only be exposed by also swapping critical sections have no witness of this
+//! form, in which case none is returned.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::tracing::{Event, Op};
+
+use super::{forget_locks, forget_variables, AccessKind, Race};
+
+fn variable(op: &Op) -> Option<((usize, usize), AccessKind)> {
+    match op {
+        Op::Read { addr, n } => Some(((*addr, *n), AccessKind::Read)),
+        Op::Write { addr, n } => Some(((*addr, *n), AccessKind::Write)),
+        _ => None,
+    }
+}
+
+// The events a single event directly depends on
+#[derive(Debug, Clone, Copy, Default)]
+struct Dependencies {
+    // The program order predecessor, or the Fork of the thread for its first event
+    previous: Option<usize>,
+    // The last event of the joined thread
+    joined: Option<usize>,
+    // The write a read reads from
+    reads_from: Option<usize>,
+    // For an Aquire, the Release that ends its critical section
+    release: Option<usize>,
+}
+
+impl Dependencies {
+    // The racing accesses themselves do not need to read the same value as in the trace, so
+    // their reads-from dependency is optional
+    fn events(&self, reads_from: bool) -> impl Iterator<Item = usize> {
+        let reads_from = self.reads_from.filter(|_| reads_from);
+        self.previous.into_iter().chain(self.joined).chain(reads_from)
+    }
+}
+
+// The dependencies of every event of `events`, in a single pass over them
+fn dependencies(events: &[Event]) -> Vec<Dependencies> {
+    let mut dependencies = vec![Dependencies::default(); events.len()];
+    let mut last: HashMap<u32, usize> = HashMap::new();
+    let mut forks: HashMap<u32, usize> = HashMap::new();
+    let mut last_write: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    // The acquisitions of every lock whose Release has not been seen yet
+    let mut open: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for (k, Event { t, op, .. }) in events.iter().enumerate() {
+        dependencies[k].previous = last.insert(*t, k).or_else(|| forks.get(t).copied());
+        match *op {
+            Op::Fork { tid } => {
+                forks.entry(tid).or_insert(k);
+            }
+            Op::Join { tid } => dependencies[k].joined = last.get(&tid).copied(),
+            Op::Read { addr, n } => dependencies[k].reads_from = last_write.get(&(addr, n)).copied(),
+            Op::Write { addr, n } => {
+                last_write.insert((addr, n), k);
+            }
+            Op::Aquire { lock } => open.entry(lock).or_default().push(k),
+            Op::Release { lock } => {
+                if let Some(acquires) = open.get_mut(&lock) {
+                    acquires.retain(|a| {
+                        let ours = events[*a].t == *t;
+                        if ours {
+                            dependencies[*a].release = Some(k);
+                        }
+                        !ours
+                    });
+                }
+            }
+            // Later accesses and acquisitions belong to an unrelated object
+            Op::Free { addr, size } => {
+                forget_variables(&mut last_write, addr, size);
+                forget_locks(&mut open, addr, size);
+            }
+            Op::Request { .. } | Op::Begin | Op::End | Op::Alloc { .. } => {}
+        }
+    }
+    dependencies
+}
+
+// Adds `roots` and everything they depend on to `needed`
+fn close(dependencies: &[Dependencies], needed: &mut BTreeSet<usize>, roots: impl IntoIterator<Item = usize>) {
+    let mut worklist: Vec<_> = roots.into_iter().collect();
+    while let Some(k) = worklist.pop() {
+        if needed.insert(k) {
+            worklist.extend(dependencies[k].events(true));
+        }
+    }
+}
+
+// The releases needed so that the critical sections in `needed` do not overlap,
+// or `None` if a critical section that has to end never does
+fn missing_releases(events: &[Event], dependencies: &[Dependencies], needed: &BTreeSet<usize>) -> Option<Vec<usize>> {
+    let mut acquires: HashMap<usize, Vec<usize>> = HashMap::new();
+    for k in needed {
+        if let Op::Aquire { lock } = events[*k].op {
+            acquires.entry(lock).or_default().push(*k);
+        }
+    }
+
+    let mut missing = Vec::new();
+    for acquires in acquires.values() {
+        for a in &acquires[..acquires.len() - 1] {
+            let release = dependencies[*a].release?;
+            if !needed.contains(&release) {
+                missing.push(release);
+            }
+        }
+    }
+    Some(missing)
+}
+
+fn witness_with(events: &[Event], dependencies: &[Dependencies], first: usize, second: usize) -> Option<Vec<Event>> {
+    let mut needed = BTreeSet::new();
+    let roots = [first, second].into_iter().flat_map(|k| dependencies[k].events(false));
+    close(dependencies, &mut needed, roots);
+    loop {
+        let missing = missing_releases(events, dependencies, &needed)?;
+        if missing.is_empty() {
+            break;
+        }
+        close(dependencies, &mut needed, missing);
+    }
+
+    // The accesses have to come last, so they must not depend on each other
+    if needed.contains(&first) || needed.contains(&second) {
+        return None;
+    }
+
+    let mut witness: Vec<_> = needed.into_iter().map(|k| events[k]).collect();
+    witness.push(events[first]);
+    witness.push(events[second]);
+    Some(witness)
+}
+
+/// Reconstructs a witness for the race between the events at the indices `first` and `second`.
+pub fn witness_at(events: &[Event], first: usize, second: usize) -> Option<Vec<Event>> {
+    witness_with(events, &dependencies(events), first, second)
+}
+
+/// Reconstructs a witness for a race reported by one of the detectors on `events`.
+///
+/// The reported accesses are only identified by thread, location and variable, so every
+/// occurrence of them is tried until a witness is found.
+pub fn witness(events: &[Event], race: &Race) -> Option<Vec<Event>> {
+    let matches = |e: &Event, access: &super::Access| {
+        e.t == access.t && e.loc == access.loc && variable(&e.op) == Some((race.variable, access.kind))
+    };
+
+    let dependencies = dependencies(events);
+    events
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| matches(e, &race.second))
+        .find_map(|(second, _)| {
+            let first = events[..second].iter().rposition(|e| matches(e, &race.first))?;
+            witness_with(events, &dependencies, first, second)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{analysis::wcp, tracing::{Event, Op}};
+
+    use super::{witness, witness_at};
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_predicted_race() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Read { addr: 24, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+            event(1, Op::Read { addr: 8, n: 4 }),
+        ];
+        let races = wcp::detect(&trace);
+        assert_eq!(races.len(), 1);
+
+        // The critical section of thread 0 comes after the write in program order, so it is left out
+        let witness = witness(&trace, &races[0]).unwrap();
+        let expected: Vec<_> = [0, 5, 6, 7, 1, 8].iter().map(|k| trace[*k]).collect();
+        assert_eq!(witness, expected);
+    }
+
+    #[test]
+    fn test_reads_from() {
+        // Thread 1 has to read what thread 0 wrote before the racing write
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Read { addr: 16, n: 4 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+        ];
+        let expected: Vec<_> = [0, 1, 3, 2, 4].iter().map(|k| trace[*k]).collect();
+        assert_eq!(witness_at(&trace, 2, 4), Some(expected));
+
+        // Here it has to read what was written after the racing write
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Write { addr: 16, n: 4 }),
+            event(1, Op::Read { addr: 16, n: 4 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+        ];
+        assert_eq!(witness_at(&trace, 1, 4), None);
+    }
+
+    #[test]
+    fn test_freed_variable() {
+        // Thread 2 reads a new object at the address thread 1 wrote before freeing it
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Fork { tid: 2 }),
+            event(1, Op::Write { addr: 16, n: 4 }),
+            event(1, Op::Free { addr: 16, size: 4 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(2, Op::Read { addr: 16, n: 4 }),
+            event(2, Op::Write { addr: 8, n: 4 }),
+        ];
+        let expected: Vec<_> = [0, 1, 5, 4, 6].iter().map(|k| trace[*k]).collect();
+        assert_eq!(witness_at(&trace, 4, 6), Some(expected));
+    }
+
+    #[test]
+    fn test_ordered_accesses() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Join { tid: 1 }),
+            event(0, Op::Read { addr: 8, n: 4 }),
+        ];
+        assert_eq!(witness_at(&trace, 1, 3), None);
+    }
+
+    #[test]
+    fn test_held_lock() {
+        let trace = [
+            event(0, Op::Fork { tid: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+            event(1, Op::Aquire { lock: 1 }),
+            event(1, Op::Write { addr: 8, n: 4 }),
+            event(1, Op::Release { lock: 1 }),
+        ];
+        assert_eq!(witness_at(&trace, 2, 5), None);
+    }
+}
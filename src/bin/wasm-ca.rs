@@ -0,0 +1,316 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufWriter, Write},
+    process::ExitCode,
+};
+
+use wasm_ca_rs::{
+    analysis,
+    error::Error,
+    tracing::{
+        location_table_path, read_binary_trace, write_std_trace, write_trace, BinaryTrace, Op, TraceFormat,
+    },
+};
+
+const USAGE: &str = "usage: wasm-ca <command> <trace.bin>
+
+commands:
+    header                      print the trace header
+    events                      print the decoded events in STD format
+    stats                       print per-thread, per-lock and per-variable statistics
+    races                       report happens-before data races
+    predict                     report data races predicted with weak-causal precedence
+    lockset                     report variables not consistently protected by a lock
+    deadlocks                   report cycles in the lock-order graph
+    atomicity                   report atomic regions that are not serializable
+    witness [-n <index>] [-o <output>]
+                                write a reordered STD trace exhibiting the n-th predicted race
+    convert -f <format> [-o <output>]
+                                convert the trace to rapidbin, std or csv
+    symbolize -w <debug.wasm> -l <module.locations> [-t <trace.loctable>]
+                                print the source position of every location of the trace,
+                                using the location table written next to it by default";
+
+enum Command {
+    Header,
+    Events,
+    Stats,
+    Races,
+    Predict,
+    Lockset,
+    Deadlocks,
+    Atomicity,
+    Witness { index: usize, output: Option<String> },
+    Convert { format: TraceFormat, output: Option<String> },
+    Symbolize { wasm: String, locations: String, table: Option<String> },
+}
+
+struct Args {
+    command: Command,
+    input: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().ok_or(USAGE)?;
+
+    let mut input = None;
+    let mut format = None;
+    let mut output = None;
+    let mut index = 0;
+    let mut wasm = None;
+    let mut locations = None;
+    let mut table = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let value = args.next().ok_or("missing value for -f")?;
+                format = Some(value.parse::<TraceFormat>().map_err(|e| e.to_string())?);
+            }
+            "-o" | "--output" => output = Some(args.next().ok_or("missing value for -o")?),
+            "-n" => {
+                let value = args.next().ok_or("missing value for -n")?;
+                index = value.parse().map_err(|_| format!("invalid race index '{value}'"))?;
+            }
+            "-w" | "--wasm" => wasm = Some(args.next().ok_or("missing value for -w")?),
+            "-l" | "--locations" => locations = Some(args.next().ok_or("missing value for -l")?),
+            "-t" | "--table" => table = Some(args.next().ok_or("missing value for -t")?),
+            "-h" | "--help" => return Err(String::from(USAGE)),
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(arg),
+            _ => return Err(format!("unexpected argument '{arg}'\n{USAGE}")),
+        }
+    }
+
+    let command = match command.as_str() {
+        "header" => Command::Header,
+        "events" => Command::Events,
+        "stats" => Command::Stats,
+        "races" => Command::Races,
+        "predict" => Command::Predict,
+        "lockset" => Command::Lockset,
+        "deadlocks" => Command::Deadlocks,
+        "atomicity" => Command::Atomicity,
+        "witness" => Command::Witness { index, output },
+        "convert" => Command::Convert {
+            format: format.ok_or(format!("convert requires -f <format>\n{USAGE}"))?,
+            output,
+        },
+        "symbolize" => Command::Symbolize {
+            wasm: wasm.ok_or(format!("symbolize requires -w <debug.wasm>\n{USAGE}"))?,
+            locations: locations.ok_or(format!("symbolize requires -l <module.locations>\n{USAGE}"))?,
+            table,
+        },
+        "-h" | "--help" => return Err(String::from(USAGE)),
+        _ => return Err(format!("unknown command '{command}'\n{USAGE}")),
+    };
+
+    Ok(Args {
+        command,
+        input: input.ok_or(USAGE)?,
+    })
+}
+
+#[derive(Default)]
+struct ThreadStats {
+    reads: u64,
+    writes: u64,
+    requests: u64,
+    acquires: u64,
+    releases: u64,
+    forks: u64,
+    joins: u64,
+    regions: u64,
+}
+
+#[derive(Default)]
+struct LockStats {
+    acquires: u64,
+    threads: BTreeSet<u32>,
+}
+
+#[derive(Default)]
+struct VariableStats {
+    reads: u64,
+    writes: u64,
+    threads: BTreeSet<u32>,
+}
+
+fn print_stats<W: Write>(trace: &BinaryTrace, mut out: W) -> Result<(), Error> {
+    let mut threads = BTreeMap::<u32, ThreadStats>::new();
+    let mut locks = BTreeMap::<usize, LockStats>::new();
+    let mut variables = BTreeMap::<usize, VariableStats>::new();
+
+    for event in &trace.events {
+        let thread = threads.entry(event.t).or_default();
+        match event.op {
+            Op::Read { addr, .. } => {
+                thread.reads += 1;
+                let variable = variables.entry(addr).or_default();
+                variable.reads += 1;
+                variable.threads.insert(event.t);
+            }
+            Op::Write { addr, .. } => {
+                thread.writes += 1;
+                let variable = variables.entry(addr).or_default();
+                variable.writes += 1;
+                variable.threads.insert(event.t);
+            }
+            Op::Request { .. } => thread.requests += 1,
+            Op::Aquire { lock } => {
+                thread.acquires += 1;
+                let lock = locks.entry(lock).or_default();
+                lock.acquires += 1;
+                lock.threads.insert(event.t);
+            }
+            Op::Release { .. } => thread.releases += 1,
+            Op::Fork { .. } => thread.forks += 1,
+            Op::Join { .. } => thread.joins += 1,
+            Op::Begin => thread.regions += 1,
+            Op::End | Op::Alloc { .. } | Op::Free { .. } => {}
+        }
+    }
+
+    writeln!(out, "threads:")?;
+    writeln!(out, "  thread\treads\twrites\trequests\tacquires\treleases\tforks\tjoins\tregions")?;
+    for (t, s) in &threads {
+        writeln!(
+            out,
+            "  T{t}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            s.reads, s.writes, s.requests, s.acquires, s.releases, s.forks, s.joins, s.regions
+        )?;
+    }
+
+    writeln!(out, "locks:")?;
+    writeln!(out, "  lock\tacquires\tthreads")?;
+    for (lock, s) in &locks {
+        writeln!(out, "  L{lock}\t{}\t{}", s.acquires, s.threads.len())?;
+    }
+
+    writeln!(out, "variables:")?;
+    writeln!(out, "  variable\treads\twrites\tthreads")?;
+    for (addr, s) in &variables {
+        writeln!(out, "  V{addr}\t{}\t{}\t{}", s.reads, s.writes, s.threads.len())?;
+    }
+
+    Ok(())
+}
+
+// Prints the innermost frame of every location ID, followed by the functions it is inlined into
+#[cfg(not(target_arch = "wasm32"))]
+fn print_symbolized<W: Write>(wasm: &str, locations: &str, table: &str, mut out: W) -> Result<(), Error> {
+    use std::io::BufReader;
+
+    use wasm_ca_rs::{location::LocationMap, symbolize::Symbolizer, tracing::read_location_table};
+
+    let locations = LocationMap::read(BufReader::new(File::open(locations)?))?;
+    let symbolizer = Symbolizer::new(&std::fs::read(wasm)?, locations)?;
+    let table = read_location_table(BufReader::new(File::open(table)?))?;
+    for (id, frames) in symbolizer.symbolize_trace(&table)? {
+        let Some((innermost, outer)) = frames.split_first() else {
+            writeln!(out, "{id}\t??")?;
+            continue;
+        };
+        writeln!(out, "{id}\t{innermost}")?;
+        for frame in outer {
+            writeln!(out, "\tinlined into {frame}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn print_symbolized<W: Write>(_: &str, _: &str, _: &str, _: W) -> Result<(), Error> {
+    Err(Error::UnsupportedEnvironment(String::from("symbolization is not available on wasm32")))
+}
+
+// Writes to a new file at `path`. Buffered writers only report errors when flushed explicitly.
+fn write_file(path: &str, f: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path)?);
+    f(&mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let trace = read_binary_trace(&std::fs::read(&args.input)?)?;
+    let mut out = BufWriter::new(std::io::stdout().lock());
+
+    match args.command {
+        Command::Header => {
+            writeln!(out, "threads:   {}", trace.header.threads)?;
+            writeln!(out, "locks:     {}", trace.header.locks)?;
+            writeln!(out, "variables: {}", trace.header.variables)?;
+            writeln!(out, "events:    {}", trace.header.events)?;
+        }
+        Command::Events => write_std_trace(&trace.events, &mut out)?,
+        Command::Stats => print_stats(&trace, &mut out)?,
+        Command::Races => {
+            for race in analysis::hb::detect(&trace.events) {
+                writeln!(out, "{race}")?;
+            }
+        }
+        Command::Predict => {
+            for race in analysis::wcp::detect(&trace.events) {
+                writeln!(out, "{race}")?;
+            }
+        }
+        Command::Lockset => {
+            for violation in analysis::lockset::detect(&trace.events) {
+                writeln!(out, "{violation}")?;
+            }
+        }
+        Command::Deadlocks => {
+            for deadlock in analysis::deadlock::detect(&trace.events) {
+                writeln!(out, "{deadlock}")?;
+            }
+        }
+        Command::Atomicity => {
+            for violation in analysis::atomicity::detect(&trace.events) {
+                writeln!(out, "{violation}")?;
+            }
+        }
+        Command::Witness { index, output } => {
+            let races = analysis::wcp::detect(&trace.events);
+            let race = races
+                .get(index)
+                .ok_or_else(|| Error::Encoding(format!("there are only {} races", races.len())))?;
+            let witness = analysis::witness::witness(&trace.events, race)
+                .ok_or_else(|| Error::Encoding(format!("no witness found for {race}")))?;
+            match output {
+                Some(path) => write_file(&path, |file| write_std_trace(&witness, file))?,
+                None => write_std_trace(&witness, &mut out)?,
+            }
+        }
+        Command::Convert { format, output } => match output {
+            Some(path) => write_file(&path, |file| write_trace(&trace.events, format, file))?,
+            None => write_trace(&trace.events, format, &mut out)?,
+        },
+        Command::Symbolize { wasm, locations, table } => {
+            let table = table.unwrap_or_else(|| location_table_path(&args.input).to_string_lossy().into_owned());
+            print_symbolized(&wasm, &locations, &table, &mut out)?
+        }
+    }
+
+    // Dropping the writer would flush it too, but silently drop a write error
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,15 +1,34 @@
-use std::fmt::Display;
+use std::{any::Any, fmt::Display};
 
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::{JsCast, JsValue};
 
 #[derive(Debug)]
 pub enum Error {
+    /// A JavaScript exception that does not fit into any of the other categories.
     JsError(String),
+    /// The worker (a WebWorker, or an OS thread natively) backing a thread could not be created.
+    WorkerSpawn(String),
+    /// A message could not be encoded for or decoded from a worker.
+    MessageEncoding(String),
+    /// The thread panicked. Contains the panic payload.
+    ThreadPanicked(Box<dyn Any + Send + 'static>),
+    /// The thread bookkeeping ended up in an inconsistent state.
+    ThreadState(&'static str),
+    /// A trace identifier exceeded the number of bits reserved for it by the output format.
+    TraceOverflow(&'static str),
+    /// The runtime environment lacks a capability required by this crate.
+    UnsupportedEnvironment(String),
+    /// An I/O operation failed.
+    Io(std::io::Error),
+    /// A trace or other data could not be encoded or decoded.
+    Encoding(String),
 }
 
-impl From<&JsValue> for Error {
-    fn from(value: &JsValue) -> Self {
-        Self::JsError(if let Some(err) = value.dyn_ref::<js_sys::Error>() {
+#[cfg(target_arch = "wasm32")]
+impl Error {
+    pub(crate) fn js_message(value: &JsValue) -> String {
+        if let Some(err) = value.dyn_ref::<js_sys::Error>() {
             String::from(err.message())
         } else if let Some(obj) = value.dyn_ref::<js_sys::Object>() {
             String::from(obj.to_string())
@@ -17,20 +36,66 @@ impl From<&JsValue> for Error {
             String::from(s)
         } else {
             format!("an unknown error occured: {value:?}")
-        })
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<&JsValue> for Error {
+    fn from(value: &JsValue) -> Self {
+        Self::JsError(Self::js_message(value))
     }
 }
 
+#[cfg(target_arch = "wasm32")]
 impl From<JsValue> for Error {
     fn from(value: JsValue) -> Self {
         Self::from(&value)
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<Error> for JsValue {
+    fn from(value: Error) -> Self {
+        js_sys::Error::new(&value.to_string()).into()
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::JsError(e) => write!(f, "{e}"),
+            Error::WorkerSpawn(e) => write!(f, "failed to spawn worker: {e}"),
+            Error::MessageEncoding(e) => write!(f, "invalid worker message: {e}"),
+            Error::ThreadPanicked(payload) => {
+                if let Some(msg) = payload.downcast_ref::<&str>() {
+                    write!(f, "thread panicked: {msg}")
+                } else if let Some(msg) = payload.downcast_ref::<String>() {
+                    write!(f, "thread panicked: {msg}")
+                } else {
+                    write!(f, "thread panicked")
+                }
+            }
+            Error::ThreadState(e) => write!(f, "invalid thread state: {e}"),
+            Error::TraceOverflow(e) => write!(f, "trace overflow: {e}"),
+            Error::UnsupportedEnvironment(e) => write!(f, "unsupported environment: {e}"),
+            Error::Io(e) => write!(f, "i/o error: {e}"),
+            Error::Encoding(e) => write!(f, "encoding error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
         }
     }
 }
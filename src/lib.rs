@@ -1,17 +1,30 @@
-mod error;
+pub mod alloc;
+pub mod analysis;
+pub mod error;
+pub mod location;
 pub mod mutex;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod symbolize;
 pub mod thread;
-mod tracing;
+pub mod tracing;
 mod wasm_abi;
 
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(target_arch = "wasm32")]
 macro_rules! console_log {
     ($($t:tt)*) => (crate::log(&format_args!($($t)*).to_string()))
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! console_log {
+    ($($t:tt)*) => (eprintln!($($t)*))
+}
+
 pub(crate) use console_log;
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
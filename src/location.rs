@@ -0,0 +1,138 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{BufRead, Write},
+};
+
+use crate::error::Error;
+
+/// Where an instrumented instruction was located in the original module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset of the instruction relative to the start of the code section.
+    ///
+    /// This is the address DWARF uses for wasm modules.
+    pub offset: u64,
+    /// Name of the enclosing function from the name section, if there is one.
+    pub function: Option<String>,
+}
+
+/// Maps the `(fidx, iidx)` pairs passed to the `wasm_abi` hooks to their original location.
+///
+/// The map is written by the instrumenter as a tab separated text file with one
+/// `fidx iidx offset function` line per location.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocationMap {
+    locations: BTreeMap<(usize, usize), Location>,
+}
+
+const HEADER: &str = "# fidx\tiidx\toffset\tfunction";
+
+impl LocationMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, loc: (usize, usize), location: Location) {
+        self.locations.insert(loc, location);
+    }
+
+    pub fn get(&self, loc: &(usize, usize)) -> Option<&Location> {
+        self.locations.get(loc)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&(usize, usize), &Location)> {
+        self.locations.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, "{HEADER}")?;
+        for ((fidx, iidx), location) in &self.locations {
+            writeln!(
+                writer,
+                "{fidx}\t{iidx}\t{}\t{}",
+                location.offset,
+                location.function.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut map = Self::new();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid =
+                || Error::Encoding(format!("invalid location map entry in line {}", number + 1));
+            let mut fields = line.splitn(4, '\t');
+            let mut next_number = || -> Result<u64, Error> {
+                fields
+                    .next()
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(invalid)
+            };
+            let fidx = next_number()? as usize;
+            let iidx = next_number()? as usize;
+            let offset = next_number()?;
+            let function = fields
+                .next()
+                .filter(|name| !name.is_empty())
+                .map(String::from);
+
+            map.insert((fidx, iidx), Location { offset, function });
+        }
+
+        Ok(map)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "{function}+{:#x}", self.offset),
+            None => write!(f, "{:#x}", self.offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Location, LocationMap};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut map = LocationMap::new();
+        map.insert(
+            (3, 7),
+            Location {
+                offset: 0x42,
+                function: Some(String::from("main")),
+            },
+        );
+        map.insert(
+            (4, 0),
+            Location {
+                offset: 0x50,
+                function: None,
+            },
+        );
+
+        let mut output = Vec::new();
+        map.write(&mut output).unwrap();
+
+        assert_eq!(LocationMap::read(output.as_slice()).unwrap(), map);
+    }
+}
@@ -5,6 +5,8 @@ use parking_lot::{
 
 use crate::wasm_abi;
 
+pub mod watchdog;
+
 pub struct TracingRawMutex {
     inner: RawMutex,
 }
@@ -20,18 +22,24 @@ unsafe impl lock_api::RawMutex for TracingRawMutex {
     fn lock(&self) {
         wasm_abi::start_lock(self as *const _ as usize);
 
-        self.inner.lock();
+        watchdog::lock(&self.inner, self as *const _ as usize);
 
         wasm_abi::finish_lock(self as *const _ as usize);
     }
 
     fn try_lock(&self) -> bool {
-        self.inner.try_lock()
+        // A try_lock never waits, so there is no Request event
+        let locked = watchdog::try_lock(&self.inner, self as *const _ as usize);
+        if locked {
+            wasm_abi::finish_lock(self as *const _ as usize);
+        }
+        locked
     }
 
     unsafe fn unlock(&self) {
         wasm_abi::start_unlock(self as *const _ as usize);
 
+        watchdog::unlock(self as *const _ as usize);
         self.inner.unlock();
 
         wasm_abi::finish_unlock(self as *const _ as usize);
@@ -39,3 +47,86 @@ unsafe impl lock_api::RawMutex for TracingRawMutex {
 }
 
 pub type TracingMutex<T> = Mutex<TracingRawMutex, T>;
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        thread::{thread_id, thread_spawn},
+        tracing::{export_trace, TraceFormat},
+    };
+
+    use super::TracingMutex;
+
+    #[test]
+    fn test_mutual_exclusion() {
+        let counter = Arc::new(TracingMutex::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread_spawn(move || {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                })
+                .unwrap()
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*counter.lock(), 4000);
+    }
+
+    #[test]
+    fn test_native_events() {
+        let mutex = Arc::new(TracingMutex::new(0));
+        // SAFETY: The raw mutex is only used for its address, which identifies the lock
+        let lock = unsafe { mutex.raw() } as *const _ as usize;
+        let child = {
+            let mutex = mutex.clone();
+            thread_spawn(move || {
+                *mutex.lock() += 1;
+                thread_id().unwrap()
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+        };
+        let main = thread_id().unwrap();
+
+        // Only a successful try_lock is recorded
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+
+        // Other tests record events concurrently, so only those of this test are compared
+        let mut csv = Vec::new();
+        export_trace(TraceFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let (lock, child_decor) = (format!("L{lock}"), format!("T{child}"));
+        let events: Vec<_> = csv
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<_> = line.split(',').collect();
+                let ours = (fields[0] == main.to_string() || fields[0] == child.to_string())
+                    && (fields[2] == lock || fields[2] == child_decor);
+                ours.then(|| format!("{},{},{}", fields[0], fields[1], fields[2]))
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                format!("{main},fork,{child_decor}"),
+                format!("{child},req,{lock}"),
+                format!("{child},acq,{lock}"),
+                format!("{child},rel,{lock}"),
+                format!("{main},join,{child_decor}"),
+                format!("{main},acq,{lock}"),
+                format!("{main},rel,{lock}"),
+            ]
+        );
+    }
+}
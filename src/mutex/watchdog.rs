@@ -0,0 +1,276 @@
+//! A watchdog that reports deadlocks between [`TracingMutex`](super::TracingMutex)es as they happen.
+//!
+//! While enabled, every lock records its owner and every thread that has to wait records a
+//! wait-for edge to the lock. If the lock is not obtained within the threshold, the wait-for
+//! graph is searched for a cycle through the waiting thread. A cycle is logged together with
+//! the recorded trace, because the deadlocked page will not get to export it anymore.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+use parking_lot::{lock_api::RawMutex as _, Mutex, RawMutex};
+
+use crate::{
+    console_log, thread,
+    tracing::{self, Event, Op, TraceFormat, TracerGuard},
+};
+
+// The threshold in milliseconds, 0 if the watchdog is disabled
+static THRESHOLD_MS: AtomicU64 = AtomicU64::new(0);
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(Mutex::default);
+
+struct Owner {
+    t: u32,
+    loc: Option<(usize, usize)>,
+}
+
+struct Waiting {
+    lock: usize,
+    loc: Option<(usize, usize)>,
+    reported: bool,
+}
+
+/// One edge of a deadlock: `t` waits for `lock`, which is held by `owner`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WaitFor {
+    t: u32,
+    lock: usize,
+    request_loc: Option<(usize, usize)>,
+    owner: u32,
+    acquire_loc: Option<(usize, usize)>,
+}
+
+#[derive(Default)]
+struct State {
+    owners: HashMap<usize, Owner>,
+    waiting: HashMap<u32, Waiting>,
+    // Request events arrive before the thread starts waiting
+    requests: HashMap<u32, (usize, usize)>,
+}
+
+impl State {
+    /// Follows the wait-for edges starting at `t` and returns them if they lead back to `t`.
+    fn find_cycle(&self, t: u32) -> Option<Vec<WaitFor>> {
+        let mut cycle = Vec::new();
+        let mut current = t;
+        while cycle.len() <= self.waiting.len() {
+            let waiting = self.waiting.get(&current)?;
+            let owner = self.owners.get(&waiting.lock)?;
+            cycle.push(WaitFor {
+                t: current,
+                lock: waiting.lock,
+                request_loc: waiting.loc,
+                owner: owner.t,
+                acquire_loc: owner.loc,
+            });
+            if owner.t == t {
+                return Some(cycle);
+            }
+            current = owner.t;
+        }
+        None
+    }
+}
+
+/// Enables the watchdog. A deadlock is detected once a lock has been waited on for `threshold`.
+///
+/// Waiting threads spin until the threshold is reached, so it should not be too large.
+pub fn enable_watchdog(threshold: Duration) {
+    THRESHOLD_MS.store((threshold.as_millis() as u64).max(1), Ordering::Relaxed);
+}
+
+pub fn disable_watchdog() {
+    THRESHOLD_MS.store(0, Ordering::Relaxed);
+    // Freeing while the state is locked must not call back into the tracer
+    let _guard = TracerGuard::enter();
+    let mut state = STATE.lock();
+    state.owners.clear();
+    state.waiting.clear();
+    state.requests.clear();
+}
+
+/// Enables the watchdog with a threshold of `threshold_ms` milliseconds.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "enable_deadlock_watchdog")]
+pub fn enable_watchdog_js(threshold_ms: u32) {
+    enable_watchdog(Duration::from_millis(u64::from(threshold_ms)));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    static START: LazyLock<std::time::Instant> = LazyLock::new(std::time::Instant::now);
+    START.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Acquires `inner`, the raw mutex behind `lock`, and keeps the wait-for graph up to date.
+pub(super) fn lock(inner: &RawMutex, lock: usize) {
+    // Allocating while the state is locked must not call back into the tracer
+    let _guard = TracerGuard::enter();
+    let threshold = THRESHOLD_MS.load(Ordering::Relaxed);
+    let t = match thread::thread_id() {
+        Ok(t) if threshold > 0 => t,
+        _ => return inner.lock(),
+    };
+
+    if !inner.try_lock() {
+        {
+            let mut state = STATE.lock();
+            let loc = state.requests.remove(&t);
+            state.waiting.insert(t, Waiting { lock, loc, reported: false });
+        }
+
+        let start = now_ms();
+        while !inner.try_lock() {
+            if now_ms() - start >= threshold as f64 {
+                check(t);
+                inner.lock();
+                break;
+            }
+            std::hint::spin_loop();
+        }
+        STATE.lock().waiting.remove(&t);
+    }
+
+    STATE.lock().owners.insert(lock, Owner { t, loc: None });
+}
+
+/// Tries to acquire `inner`, the raw mutex behind `lock`, and records the owner on success.
+pub(super) fn try_lock(inner: &RawMutex, lock: usize) -> bool {
+    let _guard = TracerGuard::enter();
+    if !inner.try_lock() {
+        return false;
+    }
+    if THRESHOLD_MS.load(Ordering::Relaxed) > 0 {
+        if let Ok(t) = thread::thread_id() {
+            STATE.lock().owners.insert(lock, Owner { t, loc: None });
+        }
+    }
+    true
+}
+
+pub(super) fn unlock(lock: usize) {
+    let _guard = TracerGuard::enter();
+    if THRESHOLD_MS.load(Ordering::Relaxed) > 0 {
+        STATE.lock().owners.remove(&lock);
+    }
+}
+
+fn check(t: u32) {
+    let _guard = TracerGuard::enter();
+    let cycle = {
+        let mut state = STATE.lock();
+        if state.waiting.get(&t).is_none_or(|waiting| waiting.reported) {
+            return;
+        }
+        let Some(cycle) = state.find_cycle(t) else { return };
+
+        // Every thread of the cycle detects it, but it is only reported once
+        for edge in &cycle {
+            if let Some(waiting) = state.waiting.get_mut(&edge.t) {
+                waiting.reported = true;
+            }
+        }
+        cycle
+    };
+
+    let format_loc = |loc: Option<(usize, usize)>| match loc {
+        Some((fidx, iidx)) => format!("{fidx}:{iidx}"),
+        None => String::from("unknown location"),
+    };
+    console_log!("Deadlock detected between {} threads:", cycle.len());
+    for edge in &cycle {
+        console_log!(
+            "    thread {} waits for lock {:#x} (requested at {}), held by thread {} (acquired at {})",
+            edge.t,
+            edge.lock,
+            format_loc(edge.request_loc),
+            edge.owner,
+            format_loc(edge.acquire_loc)
+        );
+    }
+
+    let mut trace = Vec::new();
+    match tracing::export_trace(TraceFormat::Std, &mut trace) {
+        Ok(()) => console_log!("Trace at the time of the deadlock:\n{}", String::from_utf8_lossy(&trace)),
+        Err(e) => console_log!("Could not export the trace: {e}"),
+    }
+}
+
+/// Attaches the locations of Request and Aquire events to the wait-for graph.
+pub(crate) fn observe(event: &Event) {
+    if THRESHOLD_MS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+
+    match event.op {
+        Op::Request { .. } => {
+            STATE.lock().requests.insert(event.t, event.loc);
+        }
+        Op::Aquire { lock } => {
+            if let Some(owner) = STATE.lock().owners.get_mut(&lock).filter(|o| o.t == event.t) {
+                owner.loc = Some(event.loc);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use parking_lot::lock_api::RawMutex as _;
+
+    use crate::{mutex::TracingRawMutex, thread::thread_id};
+
+    use super::{disable_watchdog, enable_watchdog, Owner, State, WaitFor, Waiting, STATE};
+
+    #[test]
+    fn test_find_cycle() {
+        let mut state = State::default();
+        state.owners.insert(10, Owner { t: 1, loc: Some((1, 1)) });
+        state.owners.insert(20, Owner { t: 2, loc: None });
+        state.waiting.insert(1, Waiting { lock: 20, loc: Some((1, 2)), reported: false });
+        assert_eq!(state.find_cycle(1), None);
+
+        state.waiting.insert(2, Waiting { lock: 10, loc: None, reported: false });
+        assert_eq!(
+            state.find_cycle(1),
+            Some(vec![
+                WaitFor { t: 1, lock: 20, request_loc: Some((1, 2)), owner: 2, acquire_loc: None },
+                WaitFor { t: 2, lock: 10, request_loc: None, owner: 1, acquire_loc: Some((1, 1)) },
+            ])
+        );
+
+        // A thread waiting for a cycle it is not part of is left to the threads in it
+        state.waiting.insert(3, Waiting { lock: 10, loc: None, reported: false });
+        assert_eq!(state.find_cycle(3), None);
+    }
+
+    #[test]
+    fn test_try_lock_owner() {
+        enable_watchdog(Duration::from_millis(100));
+        let mutex = TracingRawMutex::INIT;
+        let lock = &mutex as *const _ as usize;
+
+        assert!(mutex.try_lock());
+        assert_eq!(STATE.lock().owners.get(&lock).map(|owner| owner.t), Some(thread_id().unwrap()));
+        assert!(!mutex.try_lock());
+
+        unsafe { mutex.unlock() };
+        assert!(!STATE.lock().owners.contains_key(&lock));
+        disable_watchdog();
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    rc::Rc,
+};
+
+use gimli::{EndianRcSlice, LittleEndian};
+
+use crate::{error::Error, location::LocationMap};
+
+type Reader = EndianRcSlice<LittleEndian>;
+
+/// A source position of a location. Inlined functions yield one frame per inlining level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+/// Maps the `(fidx, iidx)` locations of a trace to source positions using the DWARF
+/// sections of the original (not instrumented) debug build.
+pub struct Symbolizer {
+    context: addr2line::Context<Reader>,
+    locations: LocationMap,
+}
+
+impl Symbolizer {
+    /// Creates a symbolizer from the original module `debug_wasm` and the location map
+    /// the instrumenter wrote for it.
+    pub fn new(debug_wasm: &[u8], locations: LocationMap) -> Result<Self, Error> {
+        let mut sections = HashMap::new();
+        for payload in wasmparser::Parser::new(0).parse_all(debug_wasm) {
+            let payload = payload.map_err(|e| Error::Encoding(e.to_string()))?;
+            if let wasmparser::Payload::CustomSection(section) = payload {
+                if section.name().starts_with(".debug_") {
+                    sections.insert(String::from(section.name()), Rc::from(section.data()));
+                }
+            }
+        }
+
+        let dwarf = gimli::Dwarf::load(|id| -> Result<Reader, gimli::Error> {
+            let data = sections
+                .get(id.name())
+                .cloned()
+                .unwrap_or_else(|| Rc::from(&[][..]));
+            Ok(EndianRcSlice::new(data, LittleEndian))
+        })
+        .map_err(|e| Error::Encoding(format!("invalid DWARF: {e}")))?;
+        let context = addr2line::Context::from_dwarf(dwarf)
+            .map_err(|e| Error::Encoding(format!("invalid DWARF: {e}")))?;
+
+        Ok(Self { context, locations })
+    }
+
+    /// Returns the frames of the location `loc`, starting with the innermost inlined function.
+    ///
+    /// Locations that are not part of the location map or not covered by the debug
+    /// information have no frames.
+    pub fn symbolize(&self, loc: &(usize, usize)) -> Result<Vec<Frame>, Error> {
+        let Some(location) = self.locations.get(loc) else {
+            return Ok(Vec::new());
+        };
+
+        let dwarf_error = |e: gimli::Error| Error::Encoding(format!("invalid DWARF: {e}"));
+        let mut frames = Vec::new();
+        let mut iter = self
+            .context
+            .find_frames(location.offset)
+            .skip_all_loads()
+            .map_err(dwarf_error)?;
+        while let Some(frame) = iter.next().map_err(dwarf_error)? {
+            let function = match frame.function {
+                Some(name) => Some(name.demangle().map_err(dwarf_error)?.into_owned()),
+                None => None,
+            };
+            let (file, line, column) = match frame.location {
+                Some(location) => (location.file.map(String::from), location.line, location.column),
+                None => (None, None, None),
+            };
+            frames.push(Frame {
+                function,
+                file,
+                line,
+                column,
+            });
+        }
+
+        Ok(frames)
+    }
+
+    /// Symbolizes the location table of a trace, i.e., `BinaryTraceBuilder::location_map`.
+    pub fn symbolize_trace(
+        &self,
+        location_map: &HashMap<(usize, usize), i16>,
+    ) -> Result<BTreeMap<i16, Vec<Frame>>, Error> {
+        location_map
+            .iter()
+            .map(|(loc, id)| Ok((*id, self.symbolize(loc)?)))
+            .collect()
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.function.as_deref().unwrap_or("??"))?;
+        if let Some(file) = &self.file {
+            write!(f, " at {file}")?;
+            if let Some(line) = self.line {
+                write!(f, ":{line}")?;
+                if let Some(column) = self.column {
+                    write!(f, ":{column}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        location::{Location, LocationMap},
+        tracing::{read_location_table, BinaryTraceBuilder, Event, Op},
+    };
+
+    use super::Symbolizer;
+
+    // Built from `tests/fixtures/store.rs`, whose only store is in line 12
+    const STORE_WASM: &[u8] = include_bytes!("../tests/fixtures/store.wasm");
+
+    // The offset of the first `i32.store` relative to the code section, as the instrumenter maps it
+    fn store_offset(wasm: &[u8]) -> u64 {
+        let mut code_section_start = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            match payload.unwrap() {
+                wasmparser::Payload::CodeSectionStart { range, .. } => code_section_start = range.start,
+                wasmparser::Payload::CodeSectionEntry(body) => {
+                    let mut operators = body.get_operators_reader().unwrap();
+                    while !operators.eof() {
+                        let (operator, offset) = operators.read_with_offset().unwrap();
+                        if let wasmparser::Operator::I32Store { .. } = operator {
+                            return (offset - code_section_start) as u64;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        panic!("the module has no store");
+    }
+
+    #[test]
+    fn test_without_debug_info() {
+        let mut locations = LocationMap::new();
+        locations.insert((0, 0), Location { offset: 1, function: None });
+        let symbolizer = Symbolizer::new(b"\0asm\x01\0\0\0", locations).unwrap();
+
+        assert!(symbolizer.symbolize(&(0, 0)).unwrap().is_empty());
+        assert!(symbolizer.symbolize(&(1, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_symbolize_trace() {
+        let mut locations = LocationMap::new();
+        locations.insert((1, 4), Location { offset: store_offset(STORE_WASM), function: None });
+        let symbolizer = Symbolizer::new(STORE_WASM, locations).unwrap();
+
+        // The store is inlined from `write_volatile` into the exported function
+        let frames = symbolizer.symbolize(&(1, 4)).unwrap();
+        let frame = frames.last().unwrap();
+        assert_eq!(frame.function.as_deref(), Some("store"));
+        assert_eq!(frame.file.as_deref(), Some("./store.rs"));
+        assert_eq!(frame.line, Some(12));
+
+        // The location table of a trace maps its IDs back to the location
+        let mut builder = BinaryTraceBuilder::new();
+        builder.push_event(&Event { t: 0, op: Op::Read { addr: 0, n: 1 }, loc: (0, 0) }).unwrap();
+        builder.push_event(&Event { t: 0, op: Op::Write { addr: 0, n: 4 }, loc: (1, 4) }).unwrap();
+        let mut table = Vec::new();
+        builder.write_location_table(&mut table).unwrap();
+        let symbolized = symbolizer.symbolize_trace(&read_location_table(table.as_slice()).unwrap()).unwrap();
+        assert!(symbolized[&0].is_empty());
+        assert_eq!(symbolized[&1], frames);
+    }
+}
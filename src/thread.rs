@@ -1,5 +1,4 @@
 use std::{
-    any::Any,
     cell::{Cell, UnsafeCell},
     panic,
     sync::{
@@ -8,17 +7,39 @@ use std::{
     },
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use native::WorkerHandle;
+#[cfg(target_arch = "wasm32")]
 use worker_handle::WorkerHandle;
 
-use crate::{console_log, error::Error, wasm_abi};
+use crate::{error::Error, wasm_abi};
 
+#[cfg(target_arch = "wasm32")]
+mod env;
+#[cfg(target_arch = "wasm32")]
 pub(crate) mod message;
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+#[cfg(target_arch = "wasm32")]
+mod node;
+#[cfg(target_arch = "wasm32")]
 mod url;
+#[cfg(target_arch = "wasm32")]
 pub(crate) mod worker_handle;
 
 // TODO: Reevaluate if this export should maybe be removed such that
 // it is only aviable via javascript.
+#[cfg(target_arch = "wasm32")]
 pub use url::set_bindgen_url_suffix_js as set_bindgen_url_suffix;
+#[cfg(target_arch = "wasm32")]
+pub use url::set_worker_url_js as set_worker_url;
+
+#[cfg(target_arch = "wasm32")]
+pub use env::{check_environment, Environment};
+
+/// The size of the shadow stack of every worker. Workers ask wasm-bindgen to allocate stacks of
+/// this size instead of its default, such that the tracer knows their bounds.
+pub(crate) const THREAD_STACK_SIZE: usize = 1 << 20;
 
 static THREAD_ID_COUNTER: AtomicU32 = AtomicU32::new(0);
 
@@ -37,30 +58,29 @@ pub struct JoinHandle<T> {
 }
 
 impl<T> JoinHandle<T> {
-    pub fn join(mut self) -> Result<T, Box<dyn Any + Send + 'static>> {
+    pub fn join(mut self) -> Result<T, Error> {
         while !self.finished.load(Ordering::Relaxed) {}
-        if let Some(internals_mut) = Arc::get_mut(&mut self.internals) {
-            if let Some(result) = internals_mut.take_result() {
-                // Terminate the WebWorker (has to be done manually)
-                self.native
-                    .terminate()
-                    .expect("Could not terminate worker!");
-
-                wasm_abi::join_thread(internals_mut.tid());
-
-                result
-            } else {
-                console_log!("Thread was marked as finished without having a result set!");
-                panic!();
-            }
-        } else {
-            console_log!("Thread was marked as finished but had more than one reference to the result struct!");
-            panic!();
-        }
+        let Some(internals_mut) = Arc::get_mut(&mut self.internals) else {
+            return Err(Error::ThreadState(
+                "thread was marked as finished but had more than one reference to the result struct",
+            ));
+        };
+        let Some(result) = internals_mut.take_result() else {
+            return Err(Error::ThreadState(
+                "thread was marked as finished without having a result set",
+            ));
+        };
+
+        // Terminate the worker (has to be done manually for WebWorkers)
+        self.native.terminate()?;
+
+        wasm_abi::join_thread(internals_mut.tid());
+
+        result
     }
 }
 
-pub type ThreadResult<T> = Result<T, Box<dyn Any + Send>>;
+pub type ThreadResult<T> = Result<T, Error>;
 
 struct ThreadInternals<T> {
     tid: u32,
@@ -109,18 +129,17 @@ fn thread_spawn_inner<F: FnOnce() -> T + Send + 'static, T: Send + 'static>(
     let write_internals = read_internals.clone();
     let write_finished = read_finished.clone();
 
+    // The worker reports the stack and TLS block of this thread before it runs `main`, see
+    // `wasm_abi::wasm_ca_thread_start`. Accesses to them are not traced.
     let main = move || {
-        // TODO: Remove the panics and find a better solution!
-        let old_id_state = THREAD_ID
+        let try_result = match THREAD_ID
             .try_with(|id_cell| id_cell.replace(Some(write_internals.tid())))
-            .expect("Thread ID has been deallocated early!");
-        assert!(
-            old_id_state.is_none(),
-            "Thread ID has already been initialized!"
-        );
-
-        // TODO: Maybe this can be omitted by using the trait boundary for UnwindSafe
-        let try_result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+        {
+            Err(_) => Err(Error::ThreadState("thread ID has been deallocated early")),
+            Ok(Some(_)) => Err(Error::ThreadState("thread ID has already been initialized")),
+            // TODO: Maybe this can be omitted by using the trait boundary for UnwindSafe
+            Ok(None) => panic::catch_unwind(panic::AssertUnwindSafe(f)).map_err(Error::ThreadPanicked),
+        };
 
         // SAFETY: `write_internals` has been defined just above and moved by the closure (being an Arc<...>).
         // `read_internals` is only given to the returned JoinHandle so the modification will not affect
@@ -148,7 +167,7 @@ fn thread_spawn_inner<F: FnOnce() -> T + Send + 'static, T: Send + 'static>(
     let main =
         unsafe { Box::from_raw(Box::into_raw(main) as *mut (dyn FnOnce() + Send + 'static)) };
 
-    let thread = WorkerHandle::spawn()?;
+    let mut thread = WorkerHandle::spawn()?;
 
     wasm_abi::spawn_thread(read_internals.tid());
 
@@ -160,11 +179,13 @@ fn thread_spawn_inner<F: FnOnce() -> T + Send + 'static, T: Send + 'static>(
     })
 }
 
-pub fn thread_spawn<F: FnOnce() -> T + Send + 'static, T: Send + 'static>(f: F) -> JoinHandle<T> {
-    thread_spawn_inner(f).expect("Thread creation failed!")
+pub fn thread_spawn<F: FnOnce() -> T + Send + 'static, T: Send + 'static>(
+    f: F,
+) -> Result<JoinHandle<T>, Error> {
+    thread_spawn_inner(f)
 }
 
-pub fn thread_id() -> u32 {
+pub fn thread_id() -> Result<u32, Error> {
     THREAD_ID
         .try_with(|id_cell| {
             if let Some(id) = id_cell.get() {
@@ -178,5 +199,26 @@ pub fn thread_id() -> u32 {
                 id
             }
         })
-        .expect("Thread ID has been deallocated early!")
+        .map_err(|_| Error::ThreadState("thread ID has been deallocated early"))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use crate::error::Error;
+
+    use super::{thread_id, thread_spawn};
+
+    #[test]
+    fn test_spawn_join() {
+        let handle = thread_spawn(|| (thread_id().unwrap(), 42)).unwrap();
+        let (tid, result) = handle.join().unwrap();
+        assert_eq!(result, 42);
+        assert_ne!(tid, thread_id().unwrap());
+    }
+
+    #[test]
+    fn test_join_panicked() {
+        let handle = thread_spawn(|| panic!("expected panic")).unwrap();
+        assert!(matches!(handle.join(), Err(Error::ThreadPanicked(_))));
+    }
 }
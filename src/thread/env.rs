@@ -0,0 +1,108 @@
+use std::sync::OnceLock;
+
+use js_sys::Reflect;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+use crate::error::Error;
+
+use super::node;
+
+#[wasm_bindgen(module = "/wasm_ca.js")]
+extern "C" {
+    #[wasm_bindgen]
+    fn supports_module_workers() -> bool;
+
+    #[wasm_bindgen]
+    fn is_shared_memory(memory: &JsValue) -> bool;
+}
+
+/// Capabilities of the JavaScript environment that are required to spawn threads.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Environment {
+    /// `SharedArrayBuffer` is available in the global scope.
+    pub shared_array_buffer: bool,
+    /// The page is cross-origin isolated (`self.crossOriginIsolated`). Always `true` under Node.js.
+    pub cross_origin_isolated: bool,
+    /// Workers can be created with `{ type: "module" }`, or `worker_threads` is available under Node.js.
+    pub module_workers: bool,
+    /// The module was built with shared memory, i.e., `wasm_bindgen::memory()` is backed by a `SharedArrayBuffer`.
+    pub shared_memory: bool,
+}
+
+#[wasm_bindgen]
+impl Environment {
+    /// Returns `true` if all capabilities are available.
+    pub fn supported(&self) -> bool {
+        self.missing().is_empty()
+    }
+}
+
+impl Environment {
+    fn detect() -> Self {
+        let global = js_sys::global();
+        let has_global = |name: &str| {
+            Reflect::get(&global, &JsValue::from_str(name))
+                .is_ok_and(|value| !value.is_undefined())
+        };
+
+        if node::is_node() {
+            return Self {
+                shared_array_buffer: has_global("SharedArrayBuffer"),
+                // Node.js does not restrict shared memory to isolated contexts
+                cross_origin_isolated: true,
+                module_workers: node::supports_node_workers(),
+                shared_memory: is_shared_memory(&wasm_bindgen::memory()),
+            };
+        }
+
+        Self {
+            shared_array_buffer: has_global("SharedArrayBuffer"),
+            cross_origin_isolated: Reflect::get(&global, &JsValue::from_str("crossOriginIsolated"))
+                .is_ok_and(|value| value.as_bool() == Some(true)),
+            module_workers: has_global("Worker") && supports_module_workers(),
+            shared_memory: is_shared_memory(&wasm_bindgen::memory()),
+        }
+    }
+
+    fn missing(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if !self.shared_array_buffer {
+            missing.push("SharedArrayBuffer is not available");
+        }
+        if !self.cross_origin_isolated {
+            missing.push("the page is not cross-origin isolated (COOP/COEP headers missing)");
+        }
+        if !self.module_workers {
+            missing.push(if node::is_node() {
+                "worker_threads are not available"
+            } else {
+                "module workers are not supported"
+            });
+        }
+        if !self.shared_memory {
+            missing.push("the wasm module was not built with shared memory");
+        }
+        missing
+    }
+
+    /// Fails with [`Error::UnsupportedEnvironment`] listing every missing capability.
+    pub fn ensure_supported(&self) -> Result<(), Error> {
+        let missing = self.missing();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedEnvironment(missing.join(", ")))
+        }
+    }
+}
+
+static ENVIRONMENT: OnceLock<Environment> = OnceLock::new();
+
+/// Reports which of the capabilities required for spawning threads are available.
+///
+/// The environment is only probed once, subsequent calls return the cached result.
+#[wasm_bindgen]
+pub fn check_environment() -> Environment {
+    *ENVIRONMENT.get_or_init(Environment::detect)
+}
@@ -1,65 +1,130 @@
 use js_sys::{BigInt, JsString, Object, Reflect};
 use wasm_bindgen::{JsCast, JsValue};
 
-use super::url::get_bindgen_url;
+use crate::error::Error;
+
+use super::{url::get_bindgen_url, THREAD_STACK_SIZE};
 
 pub enum WorkerMessage {
     Init { f_ptr: usize },
     Close,
-    Url {url: String }
+    Url { url: String, locations: String },
+    Error { message: String },
+}
+
+fn encoding_error(e: JsValue) -> Error {
+    Error::MessageEncoding(Error::js_message(&e))
 }
 
 impl WorkerMessage {
-    pub fn try_to_js(self) -> Result<JsValue, JsValue> {
+    pub fn try_to_js(self) -> Result<JsValue, Error> {
         let msg = Object::new();
 
         match self {
             WorkerMessage::Init { f_ptr } => {
-                Reflect::set(&msg, &JsValue::from_str("type"), &JsValue::from_str("init"))?;
+                Reflect::set(&msg, &JsValue::from_str("type"), &JsValue::from_str("init"))
+                    .map_err(encoding_error)?;
                 Reflect::set(
                     &msg,
                     &JsValue::from_str("url"),
-                    &JsValue::from_str(&get_bindgen_url()),
-                )?;
-                Reflect::set(&msg, &JsValue::from_str("module"), &wasm_bindgen::module())?;
-                Reflect::set(&msg, &JsValue::from_str("memory"), &wasm_bindgen::memory())?;
-                Reflect::set(&msg, &JsValue::from_str("task"), &BigInt::from(f_ptr))?;
+                    &JsValue::from_str(&get_bindgen_url()?),
+                )
+                .map_err(encoding_error)?;
+                Reflect::set(&msg, &JsValue::from_str("module"), &wasm_bindgen::module())
+                    .map_err(encoding_error)?;
+                Reflect::set(&msg, &JsValue::from_str("memory"), &wasm_bindgen::memory())
+                    .map_err(encoding_error)?;
+                Reflect::set(&msg, &JsValue::from_str("task"), &BigInt::from(f_ptr))
+                    .map_err(encoding_error)?;
+                Reflect::set(
+                    &msg,
+                    &JsValue::from_str("stack_size"),
+                    &JsValue::from_f64(THREAD_STACK_SIZE as f64),
+                )
+                .map_err(encoding_error)?;
             }
             WorkerMessage::Close => {
                 Reflect::set(
                     &msg,
                     &JsValue::from_str("type"),
                     &JsValue::from_str("close"),
-                )?;
+                )
+                .map_err(encoding_error)?;
             },
-            WorkerMessage::Url { url } => {
-                Reflect::set(&msg, &JsValue::from_str("type"), &JsValue::from_str("url"))?;
+            WorkerMessage::Url { url, locations } => {
+                Reflect::set(&msg, &JsValue::from_str("type"), &JsValue::from_str("url"))
+                    .map_err(encoding_error)?;
                 Reflect::set(
                     &msg,
                     &JsValue::from_str("url"),
                     &JsValue::from_str(&url),
-                )?;
+                )
+                .map_err(encoding_error)?;
+                Reflect::set(
+                    &msg,
+                    &JsValue::from_str("locations"),
+                    &JsValue::from_str(&locations),
+                )
+                .map_err(encoding_error)?;
+            }
+            WorkerMessage::Error { message } => {
+                Reflect::set(&msg, &JsValue::from_str("type"), &JsValue::from_str("error"))
+                    .map_err(encoding_error)?;
+                Reflect::set(
+                    &msg,
+                    &JsValue::from_str("message"),
+                    &JsValue::from_str(&message),
+                )
+                .map_err(encoding_error)?;
             }
         };
 
         Ok(msg.into())
     }
 
-    pub fn try_from_js(msg: JsValue) -> Result<Self, JsValue> {
-        let ty: String = Reflect::get(&msg, &JsValue::from_str("type"))?
-            .dyn_into::<JsString>()?
+    pub fn try_from_js(msg: JsValue) -> Result<Self, Error> {
+        let ty: String = Reflect::get(&msg, &JsValue::from_str("type"))
+            .map_err(encoding_error)?
+            .dyn_into::<JsString>()
+            .map_err(encoding_error)?
             .into();
 
         match ty.as_str() {
             "init" => {
-                let addr = Reflect::get(&msg, &JsValue::from_str("task"))?.dyn_into::<BigInt>()?;
+                let addr = Reflect::get(&msg, &JsValue::from_str("task"))
+                    .map_err(encoding_error)?
+                    .dyn_into::<BigInt>()
+                    .map_err(encoding_error)?;
+                let addr = u64::try_from(addr).map_err(|e| encoding_error(e.into()))?;
                 Ok(WorkerMessage::Init {
-                    f_ptr: u64::try_from(addr)? as usize,
+                    f_ptr: usize::try_from(addr).map_err(|_| {
+                        Error::MessageEncoding(format!("task pointer {addr} is out of range"))
+                    })?,
                 })
             }
             "close" => Ok(WorkerMessage::Close),
-            "url" => Ok(WorkerMessage::Url { url: Reflect::get(&msg, &JsValue::from_str("url"))?.dyn_into::<JsString>()?.into() }),
-            _ => panic!("Message from worker had an unknown type!"),
+            "url" => Ok(WorkerMessage::Url {
+                url: Reflect::get(&msg, &JsValue::from_str("url"))
+                    .map_err(encoding_error)?
+                    .dyn_into::<JsString>()
+                    .map_err(encoding_error)?
+                    .into(),
+                locations: Reflect::get(&msg, &JsValue::from_str("locations"))
+                    .map_err(encoding_error)?
+                    .dyn_into::<JsString>()
+                    .map_err(encoding_error)?
+                    .into(),
+            }),
+            "error" => Ok(WorkerMessage::Error {
+                message: Reflect::get(&msg, &JsValue::from_str("message"))
+                    .map_err(encoding_error)?
+                    .dyn_into::<JsString>()
+                    .map_err(encoding_error)?
+                    .into(),
+            }),
+            _ => Err(Error::MessageEncoding(format!(
+                "message from worker had an unknown type: {ty}"
+            ))),
         }
     }
 }
@@ -0,0 +1,32 @@
+use crate::error::Error;
+
+// Native counterpart of the WebWorker based `WorkerHandle`, backed by `std::thread`.
+pub struct WorkerHandle {
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl WorkerHandle {
+    pub fn spawn() -> Result<Self, Error> {
+        Ok(WorkerHandle { thread: None })
+    }
+
+    pub fn run<F: FnOnce() + Send + 'static>(&mut self, f: F) -> Result<(), Error> {
+        if self.thread.is_some() {
+            return Err(Error::ThreadState("worker has already been given work"));
+        }
+
+        let thread = std::thread::Builder::new()
+            .spawn(f)
+            .map_err(|e| Error::WorkerSpawn(e.to_string()))?;
+        self.thread = Some(thread);
+
+        Ok(())
+    }
+
+    pub fn terminate(mut self) -> Result<(), Error> {
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(Error::ThreadPanicked),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Threads under Node.js, backed by `worker_threads`.
+//!
+//! Like in browsers, the workers import the glue from the URL the main thread loaded it from and
+//! initialize it with the shared module and memory.
+//!
+//! Only the ES module glue generated by `wasm-bindgen --target web` is supported. The CommonJS
+//! glue of `--target nodejs`, which `wasm-bindgen-test --node` runs, cannot load this crate at
+//! all: it reads `import.meta.url` (see `url.rs`), which is a syntax error in CommonJS. Tests under
+//! Node.js therefore load the web glue themselves, as `tests/node.rs` does.
+
+use std::sync::OnceLock;
+
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+
+use crate::error::Error;
+
+#[wasm_bindgen(module = "/wasm_ca.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = "is_node")]
+    fn is_node_js() -> bool;
+
+    #[wasm_bindgen]
+    pub(super) fn supports_node_workers() -> bool;
+
+    #[wasm_bindgen(catch)]
+    fn spawn_node_worker(script: &str) -> Result<JsValue, JsValue>;
+}
+
+static IS_NODE: OnceLock<bool> = OnceLock::new();
+
+/// Returns `true` if the module runs under Node.js, where threads are backed by `worker_threads`.
+pub fn is_node() -> bool {
+    *IS_NODE.get_or_init(is_node_js)
+}
+
+/// Spawns a `worker_threads` worker running `wasm_ca_node_worker.js`.
+///
+/// The returned object implements the parts of the WebWorker interface used by the runtime
+/// (`postMessage`, `terminate` and `onmessage`), so it can be used as a `web_sys::Worker`.
+pub fn spawn_worker() -> Result<web_sys::Worker, Error> {
+    spawn_node_worker(include_str!("../../wasm_ca_node_worker.js"))
+        .map(JsCast::unchecked_into)
+        .map_err(|e| Error::WorkerSpawn(Error::js_message(&e)))
+}
@@ -4,40 +4,81 @@ use js_sys::{Array, Uint8Array};
 use parking_lot::Mutex;
 use wasm_bindgen::prelude::wasm_bindgen;
 
+use crate::error::Error;
+
 #[wasm_bindgen(module = "/wasm_ca.js")]
 extern "C" {
     #[wasm_bindgen]
     fn get_origin() -> String;
 }
 
+#[wasm_bindgen]
+extern "C" {
+    // Without an explicit module this import is emitted into the bindgen glue itself,
+    // so it resolves to the URL the glue module has been loaded from. This restricts the
+    // crate to ES module glue, see `node.rs`.
+    #[wasm_bindgen(thread_local_v2, js_namespace = ["import", "meta"], js_name = url)]
+    static GLUE_URL: String;
+}
+
 static BINDGEN_URL_SUFFIX: Mutex<Option<String>> = Mutex::new(None);
 
+static WORKER_URL_OVERRIDE: Mutex<Option<String>> = Mutex::new(None);
+
+// Absolute URLs are kept as they are, anything else is resolved against the page's origin.
+fn resolve_url(url: &str) -> Result<String, Error> {
+    web_sys::Url::new_with_base(url, &get_origin())
+        .map(|url| url.href())
+        .map_err(|e| Error::WorkerSpawn(format!("invalid url '{url}': {}", Error::js_message(&e))))
+}
+
+/// Overrides the URL the workers import the bindgen glue from.
+///
+/// Absolute URLs are used as they are, anything else is resolved against the page's origin.
+/// By default the URL of the glue module that loaded this crate is used. Either way it has to be
+/// ES module glue, as generated by `wasm-bindgen --target web`.
 #[wasm_bindgen(js_name = "set_bindgen_url_suffix")]
 pub fn set_bindgen_url_suffix_js(suffix: String) {
     *BINDGEN_URL_SUFFIX.lock() = Some(suffix);
 }
 
-pub fn get_bindgen_url() -> String {
-    let mut url = get_origin();
-
-    url.push_str(BINDGEN_URL_SUFFIX.lock().as_deref().unwrap_or("/index.js"));
-
-    url
+pub fn get_bindgen_url() -> Result<String, Error> {
+    match BINDGEN_URL_SUFFIX.lock().as_deref() {
+        Some(url) => resolve_url(url),
+        None => Ok(GLUE_URL.with(String::clone)),
+    }
 }
 
-static WORKER_URL: LazyLock<String> = LazyLock::new(|| {
-    let js = include_str!("worker.js");
+// The outcome is cached as a message instead of an `Error`, because the latter is not `Sync`.
+static WORKER_URL: LazyLock<Result<String, String>> = LazyLock::new(|| {
+    let js = include_str!("../../wasm_ca_worker.js");
     let options = web_sys::BlobPropertyBag::new();
     options.set_type("application/javascript");
     let blob = web_sys::Blob::new_with_u8_slice_sequence_and_options(
         Array::from_iter([Uint8Array::from(js.as_bytes())]).as_ref(),
         &options,
     )
-    .unwrap();
+    .map_err(|e| Error::js_message(&e))?;
 
-    web_sys::Url::create_object_url_with_blob(&blob).unwrap()
+    web_sys::Url::create_object_url_with_blob(&blob).map_err(|e| Error::js_message(&e))
 });
 
-pub fn get_worker_url() -> &'static str {
-    &WORKER_URL
+/// Makes workers load a statically hosted copy of `wasm_ca_worker.js` instead of a `blob:` URL.
+///
+/// This is required for pages whose Content-Security-Policy does not allow `blob:` workers
+/// (e.g., `worker-src 'self'`). Absolute URLs are used as they are, anything else is resolved
+/// against the page's origin.
+#[wasm_bindgen(js_name = "set_worker_url")]
+pub fn set_worker_url_js(url: String) {
+    *WORKER_URL_OVERRIDE.lock() = Some(url);
+}
+
+pub fn get_worker_url() -> Result<String, Error> {
+    if let Some(url) = WORKER_URL_OVERRIDE.lock().as_deref() {
+        return resolve_url(url);
+    }
+
+    WORKER_URL
+        .clone()
+        .map_err(|e| Error::WorkerSpawn(format!("could not create worker script url: {e}")))
 }
@@ -1,10 +1,9 @@
-use js_sys::Function;
 use wasm_bindgen::{prelude::{wasm_bindgen, Closure}, JsCast, JsValue};
 use web_sys::MessageEvent;
 
-use crate::error::Error;
+use crate::{console_log, error::Error};
 
-use super::{message::WorkerMessage, url::get_worker_url};
+use super::{env::check_environment, message::WorkerMessage, node, url::get_worker_url};
 
 struct Work {
     func: Box<dyn FnOnce() + Send + 'static>,
@@ -20,19 +19,42 @@ impl Work {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WorkerState {
+    // The worker has been created but has not received any work yet.
+    Idle,
+    // The worker has received its work and initializes (or already initialized) the wasm module.
+    Running,
+    // The worker has been asked to close or was terminated.
+    Closed,
+}
+
 pub struct WorkerHandle {
     worker: web_sys::Worker,
+    onmessage: Option<Closure<dyn FnMut(MessageEvent)>>,
+    state: WorkerState,
 }
 
 impl WorkerHandle {
     pub fn spawn() -> Result<Self, Error> {
-        let options = web_sys::WorkerOptions::new();
-        options.set_type(web_sys::WorkerType::Module);
+        // Fail early with a precise error instead of a cryptic one from the worker's `init`
+        check_environment().ensure_supported()?;
+
+        let worker = if node::is_node() {
+            node::spawn_worker()?
+        } else {
+            let options = web_sys::WorkerOptions::new();
+            options.set_type(web_sys::WorkerType::Module);
 
-        let worker =
-            web_sys::Worker::new_with_options(get_worker_url(), &options).map_err(Error::from)?;
+            web_sys::Worker::new_with_options(&get_worker_url()?, &options)
+                .map_err(|e| Error::WorkerSpawn(Error::js_message(&e)))?
+        };
 
-        let handle = WorkerHandle { worker };
+        let handle = WorkerHandle {
+            worker,
+            onmessage: None,
+            state: WorkerState::Idle,
+        };
 
         Ok(handle)
     }
@@ -40,51 +62,98 @@ impl WorkerHandle {
     pub fn run<
         F: FnOnce() + Send + 'static, /* TODO: Evaluate if we should put this in again ==> + Send + 'static */
     >(
-        &self,
+        &mut self,
         f: F,
     ) -> Result<(), Error> {
-        // Todo: Properly deallocate the f_ptr in case of an error!
-        self.worker
-            .post_message(
-                &WorkerMessage::Init {
-                    f_ptr: Box::into_raw(Box::new(Work::new(f))) as usize,
-                }
-                .try_to_js()
-                .map_err(Error::from)?,
-            )
-            .map_err(Error::from)
+        if self.state != WorkerState::Idle {
+            return Err(Error::ThreadState("worker has already been given work or was closed"));
+        }
+
+        let f_ptr = Box::into_raw(Box::new(Work::new(f)));
+        let sent = WorkerMessage::Init { f_ptr: f_ptr as usize }
+            .try_to_js()
+            .and_then(|msg| {
+                self.worker
+                    .post_message(&msg)
+                    .map_err(|e| Error::MessageEncoding(Error::js_message(&e)))
+            });
+
+        match sent {
+            Ok(()) => {
+                self.state = WorkerState::Running;
+                Ok(())
+            }
+            Err(e) => {
+                // SAFETY: The pointer has been created by `Box::into_raw` above and the message
+                // carrying it never reached the worker, so this is the only reference to it.
+                drop(unsafe { Box::from_raw(f_ptr) });
+                Err(e)
+            }
+        }
     }
 
-    pub fn set_onmessage(&mut self, callback: Function) {
-        let event_handler = Closure::<dyn FnMut(_)>::new(move |event: MessageEvent|  {
+    pub fn set_onmessage<F: FnMut(WorkerMessage) + 'static>(&mut self, mut callback: F) {
+        let event_handler = Closure::<dyn FnMut(_)>::new(move |event: MessageEvent| {
             match WorkerMessage::try_from_js(event.data()) {
-                Ok(msg) => if let WorkerMessage::Url { url } = msg {
-                    let _ = callback.call1(&JsValue::null(), &JsValue::from_str(&url));
-                },
-                Err(_) => todo!(),
+                Ok(msg) => callback(msg),
+                Err(e) => console_log!("Dropping message from worker: {e}"),
             }
         });
-        let event_handler = Box::new(event_handler);
-        let js_callback = event_handler.as_ref().as_ref().unchecked_ref();
-        self.worker.set_onmessage(Some(js_callback));
+        self.worker
+            .set_onmessage(Some(event_handler.as_ref().unchecked_ref()));
 
-        // FIXME: This leaks memory to stop the closure from being deallocated, which raises an error in JavaScript
-        let _ = Box::into_raw(event_handler);
+        // Replacing the closure frees the previous one, which is no longer referenced by the worker.
+        self.onmessage = Some(event_handler);
     }
 
-    pub fn terminate(self) -> Result<(), Error> {
-        self.worker
-            .post_message(&WorkerMessage::Close.try_to_js().map_err(Error::from)?)
-            .map_err(Error::from)
+    pub fn terminate(mut self) -> Result<(), Error> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        let state = std::mem::replace(&mut self.state, WorkerState::Closed);
+        match state {
+            // Let the worker deallocate its TLS and thread stack once it finished its work.
+            WorkerState::Running => {
+                let closed = WorkerMessage::Close.try_to_js().and_then(|msg| {
+                    self.worker
+                        .post_message(&msg)
+                        .map_err(|e| Error::MessageEncoding(Error::js_message(&e)))
+                });
+                if closed.is_err() {
+                    self.worker.terminate();
+                }
+                closed
+            }
+            // The wasm module was never instantiated in the worker, so there is nothing to clean up.
+            WorkerState::Idle => {
+                self.worker.terminate();
+                Ok(())
+            }
+            WorkerState::Closed => Ok(()),
+        }
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            console_log!("Could not close worker: {e}");
+        }
+        // Detach the closure before it is freed together with the handle.
+        if self.onmessage.is_some() {
+            self.worker.set_onmessage(None);
+        }
     }
 }
 
 #[wasm_bindgen(js_name = "handle_msg")]
-pub fn handle_js_message(msg: JsValue) -> Result<(), JsValue> {
+pub fn handle_js_message(msg: JsValue) -> Result<(), Error> {
     match WorkerMessage::try_from_js(msg)? {
         WorkerMessage::Init { f_ptr } => execute_work(f_ptr),
         WorkerMessage::Close => (), // Noop, because this msg is handled in JS,
-        WorkerMessage::Url { url: _ } => () // This serves only for internal onmessage callbacks
+        // These serve only for internal onmessage callbacks
+        WorkerMessage::Url { .. } | WorkerMessage::Error { .. } => (),
     }
     Ok(())
 }
@@ -1,13 +1,29 @@
-use js_sys::{Array, Uint8Array};
+use std::{
+    cell::Cell,
+    io::Write,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
 use parking_lot::Mutex;
-use rapidbin::BinaryTraceBuilder;
-use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
-use web_sys::DedicatedWorkerGlobalScope;
 
-use crate::thread::{self, message::WorkerMessage, worker_handle::{WorkerHandle}};
+use crate::{analysis, console_log, error::Error, mutex::watchdog, thread, wasm_abi};
 
+mod clock;
+#[cfg(target_arch = "wasm32")]
+mod download;
+pub(crate) mod private;
 mod rapidbin;
+mod text;
+mod timeline;
+mod variables;
+
+pub use rapidbin::{read_binary_trace, read_location_table, BinaryTrace, BinaryTraceBuilder, TraceHeader};
+pub use text::{write_csv_trace, write_std_trace};
+pub use timeline::{write_metadata, write_timeline};
+pub use variables::{split_events, Granularity};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Op {
     Read { addr: usize, n: usize },
     Write { addr: usize, n: usize },
@@ -16,6 +32,15 @@ pub enum Op {
     Release { lock: usize },
     Fork { tid: u32 },
     Join { tid: u32 },
+    /// Start of an atomic region, see [`atomic_region`].
+    Begin,
+    /// End of an atomic region.
+    End,
+    /// A heap allocation of `size` bytes at `addr`, see [`TracingAllocator`](crate::alloc::TracingAllocator).
+    Alloc { addr: usize, size: usize },
+    /// The allocation at `addr` was freed. Variables and locks in it get a fresh identity once
+    /// the memory is reused.
+    Free { addr: usize, size: usize },
 }
 
 impl Op {
@@ -28,53 +53,365 @@ impl Op {
             Op::Release { lock: _ } => 1,
             Op::Fork { tid: _ } => 4,
             Op::Join { tid: _ } => 5,
+            Op::Begin => 6,
+            Op::End => 7,
+            // Not part of RAPID's encoding, these are never written to RapidBin traces
+            Op::Alloc { addr: _, size: _ } => 9,
+            Op::Free { addr: _, size: _ } => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub t: u32,              // ID of the executing thread
+    pub op: Op,              // executed operation
+    pub loc: (usize, usize), // location in the program: (function_idx, instr_idx)
+}
+
+/// The location of events that are not emitted by instrumented code.
+pub const UNKNOWN_LOCATION: (usize, usize) = (usize::MAX, usize::MAX);
+
+/// The formats a trace can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// RAPID's binary format, see [`BinaryTraceBuilder`].
+    RapidBin,
+    /// RAPID's textual STD format, see [`write_std_trace`].
+    Std,
+    /// Comma separated values, see [`write_csv_trace`].
+    Csv,
+}
+
+impl FromStr for TraceFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rapidbin" | "bin" => Ok(TraceFormat::RapidBin),
+            "std" => Ok(TraceFormat::Std),
+            "csv" => Ok(TraceFormat::Csv),
+            _ => Err(Error::Encoding(format!("unknown trace format '{s}'"))),
+        }
+    }
+}
+
+/// Writes `events` to `writer` in the given format.
+pub fn write_trace<W: Write>(events: &[Event], format: TraceFormat, mut writer: W) -> Result<(), Error> {
+    match format {
+        TraceFormat::RapidBin => {
+            let mut builder = BinaryTraceBuilder::new();
+            for e in events {
+                builder.push_event(e)?;
+            }
+            writer.write_all(&builder.build())?;
+            Ok(())
         }
+        TraceFormat::Std => write_std_trace(events, writer),
+        TraceFormat::Csv => write_csv_trace(events, writer),
     }
 }
 
-struct Event {
-    t: u32,              // ID of the executing thread
-    op: Op,              // executed operation
-    loc: (usize, usize), // location in the program: (function_idx, instr_idx)
+struct Trace {
+    events: Vec<Event>,
+    // The index and timestamp of every event that has one, events recorded while timestamps
+    // are disabled have none
+    timestamps: Vec<(usize, u64)>,
+}
+
+impl Trace {
+    const fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, event: Event, timestamp: Option<u64>) {
+        if let Some(timestamp) = timestamp {
+            self.timestamps.push((self.events.len(), timestamp));
+        }
+        self.events.push(event);
+    }
+
+    fn timestamps(&self) -> Vec<Option<u64>> {
+        let mut timestamps = vec![None; self.events.len()];
+        for (i, timestamp) in &self.timestamps {
+            timestamps[*i] = Some(*timestamp);
+        }
+        timestamps
+    }
 }
 
-static TRACE: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+static TRACE: Mutex<Trace> = Mutex::new(Trace::new());
 
+static RECORDING: AtomicBool = AtomicBool::new(true);
+
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+static GRANULARITY: Mutex<Granularity> = Mutex::new(Granularity::Exact);
+
+/// Sets whether events are added to the trace. Online analyses see them either way.
+pub fn set_recording(enabled: bool) {
+    RECORDING.store(enabled, Ordering::Relaxed);
+}
+
+thread_local! {
+    static IN_TRACER: Cell<bool> = const { Cell::new(false) };
+}
+
+// Marks the current thread as being inside the tracer until dropped. The tracer allocates and
+// may itself be instrumented, so events it would emit itself have to be dropped instead of
+// recording them recursively.
+pub(crate) struct TracerGuard;
+
+impl TracerGuard {
+    // Inlined into the hooks, which are not instrumented, such that nothing runs before the flag is set
+    #[inline(always)]
+    pub(crate) fn enter() -> Option<Self> {
+        // Not `then_some`, whose guard would be dropped on failure and clear the flag of the caller
+        if enter_tracer() {
+            Some(TracerGuard)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for TracerGuard {
+    fn drop(&mut self) {
+        exit_tracer();
+    }
+}
+
+// The instrumenter finds these by name and leaves them and everything they call uninstrumented,
+// as an access in here would call a hook before the flag is set.
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn enter_tracer() -> bool {
+    // Without thread locals (during thread teardown) it is not safe to record anything
+    IN_TRACER.try_with(|flag| !flag.replace(true)).unwrap_or(false)
+}
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn exit_tracer() {
+    let _ = IN_TRACER.try_with(|flag| flag.set(false));
+}
+
+/// Sets whether recorded events are timestamped, see [`export_timeline`] and [`export_metadata`].
+pub fn set_timestamps(enabled: bool) {
+    TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+/// Sets whether recorded events are timestamped.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "set_timestamps")]
+pub fn set_timestamps_js(enabled: bool) {
+    set_timestamps(enabled);
+}
+
+/// Sets how accesses are mapped to variables by the exporters and online analyses.
+///
+/// Fails for a [`Granularity::Word`] whose size is not a power of two.
+pub fn set_granularity(granularity: Granularity) -> Result<(), Error> {
+    *GRANULARITY.lock() = granularity.validate()?;
+    Ok(())
+}
+
+// Not locked while the events are split, which can take a while
+fn granularity() -> Granularity {
+    *GRANULARITY.lock()
+}
+
+/// Sets the granularity from its name: `exact`, `byte`, `word`, `word:<size>` or `interval`.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "set_granularity")]
+pub fn set_granularity_js(granularity: &str) -> Result<(), Error> {
+    set_granularity(granularity.parse()?)
+}
+
+/// Records an event of the current thread, unless it is already inside the tracer.
 #[inline]
 pub fn add_event(op: Op, loc: (usize, usize)) {
-    let event = Event {
-        t: thread::thread_id(),
-        op,
-        loc,
+    if let Some(_guard) = TracerGuard::enter() {
+        record_event(op, loc);
+    }
+}
+
+// Records an event, the caller has to hold a `TracerGuard`
+pub(crate) fn record_event(op: Op, loc: (usize, usize)) {
+    if private::is_private(&op) {
+        return;
+    }
+    let t = match thread::thread_id() {
+        Ok(t) => t,
+        Err(e) => {
+            console_log!("Dropping trace event: {e}");
+            return;
+        }
     };
-    TRACE.lock().push(event);
+    let event = Event { t, op, loc };
+    for split in variables::split_online(&event, granularity()) {
+        analysis::hb::observe(&split);
+        analysis::fasttrack::observe(&split);
+    }
+    watchdog::observe(&event);
+    if RECORDING.load(Ordering::Relaxed) {
+        let mut trace = TRACE.lock();
+        // Taken under the lock, so that the timestamps are ordered like the events
+        let timestamp = TIMESTAMPS.load(Ordering::Relaxed).then(clock::now_us);
+        trace.push(event, timestamp);
+    }
 }
 
-#[wasm_bindgen]
-pub fn generate_trace_download_url(callback: js_sys::Function) -> Result<(), JsValue> {
-    let mut worker = WorkerHandle::spawn().map_err(|e| JsValue::from_str(&format!("{e}")))?;
-    worker.set_onmessage(callback);
-    worker.run(move || {
-        let mut output = BinaryTraceBuilder::new();
+// Ends the region on drop, so that a panic does not leave it open
+struct AtomicRegionGuard;
 
-        for e in TRACE.lock().iter() {
-            output.push_event(e);
-        }
+impl Drop for AtomicRegionGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        wasm_abi::end_atomic();
+    }
+}
+
+/// Runs `f` as an atomic region, which the atomicity analysis checks to be serializable.
+///
+/// Regions can be nested, only the outermost one is considered. This function is always
+/// inlined, so that the Begin and End events carry the location of its caller.
+#[inline(always)]
+pub fn atomic_region<R>(f: impl FnOnce() -> R) -> R {
+    wasm_abi::begin_atomic();
+    let _guard = AtomicRegionGuard;
+    f()
+}
+
+// Encodes the events recorded so far, the caller has to hold a `TracerGuard`
+fn build() -> Result<BinaryTraceBuilder, Error> {
+    let mut output = BinaryTraceBuilder::new();
+
+    let granularity = granularity();
+    for e in split_events(&TRACE.lock().events, granularity) {
+        output.push_event(&e)?;
+    }
+
+    Ok(output)
+}
+
+/// Encodes the events recorded so far as a RapidBin trace.
+pub fn build_trace() -> Result<Vec<u8>, Error> {
+    // Allocating while the trace is locked must not call back into the tracer
+    let _guard = TracerGuard::enter();
+    Ok(build()?.build())
+}
 
-        let options = web_sys::BlobPropertyBag::new();
-        options.set_type("application/octet-stream");
-        let blob = web_sys::Blob::new_with_u8_slice_sequence_and_options(
-            Array::from_iter([Uint8Array::from(output.build().as_slice())]).as_ref(),
-            &options,
-        )
-        .unwrap();
-
-        let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
-        
-        // This is fine as we are guaranteed to be in a worker by implementation
-        let global = js_sys::global().unchecked_into::<DedicatedWorkerGlobalScope>();
-        let _ = global.post_message(&WorkerMessage::Url { url }.try_to_js().unwrap());
-    }).map_err(|e| JsValue::from_str(&format!("{e}")))?;
+/// Encodes the events recorded so far as a RapidBin trace together with its location table,
+/// see [`BinaryTraceBuilder::write_location_table`].
+pub fn build_trace_with_locations() -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let _guard = TracerGuard::enter();
+    let output = build()?;
+    let mut locations = Vec::new();
+    output.write_location_table(&mut locations)?;
+    Ok((output.build(), locations))
+}
+
+/// Writes the location table of the trace [`build_trace`] would return now. Locations keep
+/// their IDs as the trace grows, so the table also covers traces built earlier.
+pub fn export_location_table<W: Write>(writer: W) -> Result<(), Error> {
+    let _guard = TracerGuard::enter();
+    build()?.write_location_table(writer)
+}
+
+/// The path of the location table that [`write_trace_file`] writes next to the trace at `trace`.
+pub fn location_table_path<P: AsRef<std::path::Path>>(trace: P) -> std::path::PathBuf {
+    let mut path = trace.as_ref().as_os_str().to_owned();
+    path.push(".loctable");
+    path.into()
+}
+
+/// Writes the events recorded so far to `writer` in the given format.
+///
+/// RapidBin traces only refer to their locations by ID, see [`export_location_table`].
+pub fn export_trace<W: Write>(format: TraceFormat, writer: W) -> Result<(), Error> {
+    let _guard = TracerGuard::enter();
+    // Not a tail expression, the trace has to be unlocked before the guard is dropped
+    let granularity = granularity();
+    let events = split_events(&TRACE.lock().events, granularity);
+    write_trace(&events, format, writer)
+}
+
+/// Writes the events recorded so far as a timeline, see [`write_timeline`].
+pub fn export_timeline<W: Write>(writer: W) -> Result<(), Error> {
+    let _guard = TracerGuard::enter();
+    let (events, timestamps) = {
+        let trace = TRACE.lock();
+        (trace.events.clone(), trace.timestamps())
+    };
+    write_timeline(&events, &timestamps, writer)
+}
+
+/// Writes the timestamps of the events recorded so far, matching the events of [`build_trace`].
+pub fn export_metadata<W: Write>(writer: W) -> Result<(), Error> {
+    let _guard = TracerGuard::enter();
+    let granularity = granularity();
+    let (events, timestamps) = {
+        let trace = TRACE.lock();
+        variables::split_timed_events(&trace.events, &trace.timestamps(), granularity)
+    };
+    write_metadata(&events, &timestamps, writer)
+}
 
+/// Profiles the lock contention in the events recorded so far, see [`analysis::contention`].
+pub fn profile_contention() -> Vec<analysis::contention::LockProfile> {
+    let _guard = TracerGuard::enter();
+    let (events, timestamps) = {
+        let trace = TRACE.lock();
+        (trace.events.clone(), trace.timestamps())
+    };
+    analysis::contention::profile(&events, &timestamps)
+}
+
+/// Returns the timeline of the events recorded so far as JSON, see [`write_timeline`].
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "export_timeline")]
+pub fn export_timeline_js() -> Result<String, Error> {
+    let mut timeline = Vec::new();
+    export_timeline(&mut timeline)?;
+    Ok(String::from_utf8_lossy(&timeline).into_owned())
+}
+
+/// Returns the timestamps of the events recorded so far as JSON, see [`write_metadata`].
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen(js_name = "export_metadata")]
+pub fn export_metadata_js() -> Result<String, Error> {
+    let mut metadata = Vec::new();
+    export_metadata(&mut metadata)?;
+    Ok(String::from_utf8_lossy(&metadata).into_owned())
+}
+
+/// Writes the events recorded so far as a RapidBin trace to `path` and its location table to
+/// [`location_table_path`].
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_trace_file<P: AsRef<std::path::Path>>(path: P) -> Result<(), Error> {
+    let (trace, locations) = build_trace_with_locations()?;
+    std::fs::write(&path, trace)?;
+    std::fs::write(location_table_path(path), locations)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Event, Op, Trace};
+
+    #[test]
+    fn test_trace_timestamps() {
+        let mut trace = Trace::new();
+        let event = Event { t: 0, op: Op::Begin, loc: (0, 0) };
+        for timestamp in [None, Some(5), None, None, Some(9), None] {
+            trace.push(event, timestamp);
+        }
+        assert_eq!(trace.timestamps, [(1, 5), (4, 9)]);
+        assert_eq!(trace.timestamps(), [None, Some(5), None, None, Some(9), None]);
+    }
+}
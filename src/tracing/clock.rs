@@ -0,0 +1,33 @@
+//! The clock events are timestamped with, in microseconds.
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static PERFORMANCE: Option<web_sys::Performance> = {
+        use wasm_bindgen::JsCast;
+
+        js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+            .ok()
+            .and_then(|performance| performance.dyn_into().ok())
+    };
+}
+
+/// Microseconds since the Unix epoch, from `performance.now()`.
+///
+/// Every worker has a time origin of its own, which is added so that the timestamps of
+/// different threads can be compared.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_us() -> u64 {
+    PERFORMANCE
+        .try_with(|performance| match performance {
+            Some(performance) => ((performance.time_origin() + performance.now()) * 1000.0) as u64,
+            None => (js_sys::Date::now() * 1000.0) as u64,
+        })
+        .unwrap_or(0)
+}
+
+/// Microseconds since the first timestamp was taken.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_us() -> u64 {
+    static START: std::sync::LazyLock<std::time::Instant> = std::sync::LazyLock::new(std::time::Instant::now);
+    START.elapsed().as_micros() as u64
+}
@@ -0,0 +1,76 @@
+use std::{cell::RefCell, rc::Rc};
+
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use web_sys::DedicatedWorkerGlobalScope;
+
+use crate::{
+    console_log,
+    error::Error,
+    thread::{message::WorkerMessage, worker_handle::WorkerHandle},
+};
+
+use super::build_trace_with_locations;
+
+fn create_download_url(bytes: &[u8], mime_type: &str) -> Result<String, Error> {
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = web_sys::Blob::new_with_u8_slice_sequence_and_options(
+        Array::from_iter([Uint8Array::from(bytes)]).as_ref(),
+        &options,
+    )?;
+
+    Ok(web_sys::Url::create_object_url_with_blob(&blob)?)
+}
+
+// The trace and its location table are built together, such that their IDs match
+fn create_trace_download_urls() -> Result<WorkerMessage, Error> {
+    let (trace, locations) = build_trace_with_locations()?;
+    Ok(WorkerMessage::Url {
+        url: create_download_url(&trace, "application/octet-stream")?,
+        locations: create_download_url(&locations, "text/tab-separated-values")?,
+    })
+}
+
+/// Calls `callback` with a download URL of the RapidBin trace of the events recorded so far and
+/// one of its location table, see [`BinaryTraceBuilder::write_location_table`](super::BinaryTraceBuilder::write_location_table).
+#[wasm_bindgen]
+pub fn generate_trace_download_url(callback: js_sys::Function) -> Result<(), Error> {
+    // The handle has to outlive this function until the worker answers. It is kept alive by the
+    // message handler and dropped (terminating the worker and freeing the handler) on answer.
+    let slot = Rc::new(RefCell::new(None));
+    let handler_slot = slot.clone();
+
+    let mut worker = WorkerHandle::spawn()?;
+    worker.set_onmessage(move |msg| {
+        match msg {
+            WorkerMessage::Url { url, locations } => {
+                let _ = callback.call2(&JsValue::null(), &JsValue::from_str(&url), &JsValue::from_str(&locations));
+            }
+            WorkerMessage::Error { message } => {
+                console_log!("Could not generate trace download url: {message}")
+            }
+            _ => {}
+        }
+        let worker: Option<WorkerHandle> = handler_slot.borrow_mut().take();
+        drop(worker);
+    });
+    worker.run(move || {
+        // The main thread only frees the worker once it answers, so it has to answer on errors too.
+        let msg = create_trace_download_urls()
+            .and_then(WorkerMessage::try_to_js)
+            .or_else(|e| WorkerMessage::Error { message: e.to_string() }.try_to_js());
+
+        match msg {
+            Ok(msg) => {
+                // This is fine as we are guaranteed to be in a worker by implementation
+                let global = js_sys::global().unchecked_into::<DedicatedWorkerGlobalScope>();
+                let _ = global.post_message(&msg);
+            }
+            Err(e) => console_log!("Could not report the trace download url: {e}"),
+        }
+    })?;
+    *slot.borrow_mut() = Some(worker);
+
+    Ok(())
+}
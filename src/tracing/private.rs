@@ -0,0 +1,70 @@
+//! The shadow stack and TLS block of a thread, which no other thread ever accesses.
+//!
+//! Accesses to them make up most of a trace, but can never race. Spawned threads learn their
+//! ranges when their worker starts through the `thread_memory` intrinsic, and their accesses
+//! inside them are dropped before they reach the trace. The main thread and native threads do not learn
+//! them, so all of their accesses are kept.
+
+use std::cell::Cell;
+
+use crate::thread::THREAD_STACK_SIZE;
+
+use super::Op;
+
+thread_local! {
+    // Start and end of the stack and of the TLS block
+    static PRIVATE: Cell<[(usize, usize); 2]> = const { Cell::new([(0, 0); 2]) };
+}
+
+// The stack grows downwards from its top, and wasm-bindgen allocated `THREAD_STACK_SIZE` bytes for it
+fn ranges(stack_top: usize, tls_base: usize, tls_size: usize) -> [(usize, usize); 2] {
+    [(stack_top.saturating_sub(THREAD_STACK_SIZE), stack_top), (tls_base, tls_base.saturating_add(tls_size))]
+}
+
+/// Sets the private memory of the current thread from the top of its empty stack and its TLS block.
+pub(crate) fn set_thread_memory(stack_top: usize, tls_base: usize, tls_size: usize) {
+    let _ = PRIVATE.try_with(|private| private.set(ranges(stack_top, tls_base, tls_size)));
+}
+
+/// Whether `op` only accesses the private memory of the current thread.
+pub(crate) fn is_private(op: &Op) -> bool {
+    let (Op::Read { addr, n } | Op::Write { addr, n }) = *op else {
+        return false;
+    };
+    PRIVATE
+        .try_with(|private| {
+            private
+                .get()
+                .iter()
+                .any(|(start, end)| *start <= addr && addr.saturating_add(n) <= *end)
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{thread::THREAD_STACK_SIZE, tracing::Op};
+
+    use super::{is_private, ranges, set_thread_memory};
+
+    #[test]
+    fn test_private_memory() {
+        let sp = 2 * THREAD_STACK_SIZE;
+        assert!(!is_private(&Op::Read { addr: sp - 8, n: 4 }));
+
+        set_thread_memory(sp, 64, 32);
+        assert!(is_private(&Op::Read { addr: sp - 8, n: 4 }));
+        assert!(is_private(&Op::Write { addr: 64, n: 8 }));
+        assert!(!is_private(&Op::Write { addr: 92, n: 8 }));
+        assert!(!is_private(&Op::Read { addr: sp, n: 4 }));
+        assert!(is_private(&Op::Read { addr: THREAD_STACK_SIZE, n: 4 }));
+        assert!(!is_private(&Op::Read { addr: THREAD_STACK_SIZE - 4, n: 4 }));
+        assert!(!is_private(&Op::Aquire { lock: sp - 8 }));
+    }
+
+    #[test]
+    fn test_ranges_saturate() {
+        // Bogus values at either end of the address space must not overflow
+        assert_eq!(ranges(8, usize::MAX - 4, 8), [(0, 8), (usize::MAX - 4, usize::MAX)]);
+    }
+}
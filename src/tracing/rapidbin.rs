@@ -1,15 +1,20 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, Write},
+};
 
-use super::Event;
+use crate::error::Error;
 
-/*
-Only relevant for reading traces:
+use super::{Event, Op};
 
 static NUMBER_OF_TRHEADS_MASK: i16  = 0x7FFF;
 static NUMBER_OF_LOCKS_MASK: i32    = 0x7FFFFFFF;
 static NUMBER_OF_VARS_MASK: i32     = 0x7FFFFFFF;
 static NUMBER_OF_EVENTS_MASK: i64   = 0x7FFFFFFFFFFFFFFF;
-*/
+
+static HEADER_SIZE: usize = 18;
+
+const LOCATION_TABLE_HEADER: &str = "# id\tfidx\tiidx";
 
 static THREAD_NUM_BITS: u16 = 10;
 static THREAD_BIT_OFFSET: u16 = 0;
@@ -23,21 +28,25 @@ static DECOR_BIT_OFFSET: u16 = THREAD_NUM_BITS + OP_NUM_BITS;
 static LOC_NUM_BITS: u16 = 15;
 static LOC_BIT_OFFSET: u16 = THREAD_NUM_BITS + OP_NUM_BITS + DECOR_NUM_BITS;
 
-/*
-Only relevant for reading traces:
-
 static THREAD_MASK: i64 = ((1 << THREAD_NUM_BITS) - 1) << THREAD_BIT_OFFSET;
 static OP_MASK: i64 = ((1 << OP_NUM_BITS) - 1) << OP_BIT_OFFSET;
 static DECOR_MASK: i64 = ((1 << DECOR_NUM_BITS) - 1) << DECOR_BIT_OFFSET;
 static LOC_MASK: i64 = ((1 << LOC_NUM_BITS) - 1) << LOC_BIT_OFFSET;
-*/
+
+fn check_bits(value: i64, bits: u16, msg: &'static str) -> Result<i64, Error> {
+    if (0..(1 << bits)).contains(&value) {
+        Ok(value)
+    } else {
+        Err(Error::TraceOverflow(msg))
+    }
+}
 
 pub struct BinaryTraceBuilder {
     thread_map: HashMap<u32, i16>,
     thread_counter: i16,
-    memory_map: HashMap<(usize, usize), i32>,
+    memory_map: BTreeMap<(usize, usize), i32>,
     memory_counter: i32,
-    lock_map: HashMap<usize, i32>,
+    lock_map: BTreeMap<usize, i32>,
     lock_counter: i32,
     location_map: HashMap<(usize, usize), i16>,
     location_counter: i16,
@@ -50,9 +59,9 @@ impl BinaryTraceBuilder {
         Self { 
             thread_map: HashMap::new(), 
             thread_counter: 0, 
-            memory_map: HashMap::new(), 
+            memory_map: BTreeMap::new(), 
             memory_counter: 0, 
-            lock_map: HashMap::new(), 
+            lock_map: BTreeMap::new(), 
             lock_counter: 0, 
             location_map: HashMap::new(),
             location_counter: 0,
@@ -61,85 +70,134 @@ impl BinaryTraceBuilder {
         }
     }
 
-    fn get_thread_identifier(&mut self, t: &u32) -> i16 {
+    fn get_thread_identifier(&mut self, t: &u32) -> Result<i16, Error> {
         if let Some(tid) = self.thread_map.get(t) {
-            *tid
+            Ok(*tid)
         } else {
             let tid = self.thread_counter;
+            self.thread_counter = tid.checked_add(1).ok_or(Error::TraceOverflow("too many threads"))?;
             self.thread_map.insert(*t, tid);
-            self.thread_counter += 1;
-            tid
+            Ok(tid)
         }
     }
 
-    fn get_location_identifier(&mut self, loc: &(usize, usize)) -> i16 {
+    fn get_location_identifier(&mut self, loc: &(usize, usize)) -> Result<i16, Error> {
         if let Some(loc_id) = self.location_map.get(loc) {
-            *loc_id
+            Ok(*loc_id)
         } else {
             let loc_id = self.location_counter;
+            self.location_counter = loc_id.checked_add(1).ok_or(Error::TraceOverflow("too many locations"))?;
             self.location_map.insert(*loc, loc_id);
-            self.location_counter += 1;
-            loc_id
+            Ok(loc_id)
         }
     }
 
-    fn get_memory_identifier(&mut self, addr: &usize, n: &usize) -> i32 {
+    fn get_memory_identifier(&mut self, addr: &usize, n: &usize) -> Result<i32, Error> {
         if let Some(mem_id) = self.memory_map.get(&(*addr, *n)) {
-            *mem_id
+            Ok(*mem_id)
         } else {
             let mem_id = self.memory_counter;
+            self.memory_counter = mem_id.checked_add(1).ok_or(Error::TraceOverflow("too many variables"))?;
             self.memory_map.insert((*addr, *n), mem_id);
-            self.memory_counter += 1;
-            mem_id
+            Ok(mem_id)
         }
     }
 
-    fn get_lock_identifier(&mut self, lock: &usize) -> i32 {
+    fn get_lock_identifier(&mut self, lock: &usize) -> Result<i32, Error> {
         if let Some(lock_id) = self.lock_map.get(lock) {
-            *lock_id
+            Ok(*lock_id)
         } else {
             let lock_id = self.lock_counter;
+            self.lock_counter = lock_id.checked_add(1).ok_or(Error::TraceOverflow("too many locks"))?;
             self.lock_map.insert(*lock, lock_id);
-            self.lock_counter += 1;
-            lock_id
+            Ok(lock_id)
         }
     }
 
-    fn convert_event(&mut self, event: &Event) -> i64 {
+    fn convert_event(&mut self, event: &Event) -> Result<i64, Error> {
         let Event{t, op, loc} = event;
 
-        // TODO: Look at this part again:
-        // We clip the values of the event elements here to ensure they have at maximum their
-        // designated number of bits set for the OR operation later on. 
-        // However, the program currently does not check, whether the value was in the valid
-        // range or not possibly resulting in silent errors (invalid traces).
-        let thread_id = i64::from(self.get_thread_identifier(t)) & ((1 << THREAD_NUM_BITS) - 1);
-        let op_id = i64::from(op.id()) & ((1 << OP_NUM_BITS) - 1);    
-        let location_id = i64::from(self.get_location_identifier(loc)) & ((1 << LOC_NUM_BITS) - 1);
+        // Every identifier must fit into its designated number of bits, because it would
+        // otherwise be clipped by the OR operation below and silently produce an invalid trace.
+        let thread_id = i64::from(self.get_thread_identifier(t)?);
+        let op_id = i64::from(op.id());
+        let location_id = i64::from(self.get_location_identifier(loc)?);
         let decor = i64::from(match op {
             super::Op::Read { addr, n } |
-            super::Op::Write { addr, n } => self.get_memory_identifier(addr, n),
+            super::Op::Write { addr, n } => self.get_memory_identifier(addr, n)?,
             super::Op::Aquire { lock } |
             super::Op::Request { lock } |
-            super::Op::Release { lock } => self.get_lock_identifier(lock),
+            super::Op::Release { lock } => self.get_lock_identifier(lock)?,
             super::Op::Fork { tid } |
-            super::Op::Join { tid } => i32::from(self.get_thread_identifier(tid)),
-        }) & ((1 << DECOR_NUM_BITS) - 1);
+            super::Op::Join { tid } => i32::from(self.get_thread_identifier(tid)?),
+            super::Op::Begin |
+            super::Op::End => 0,
+            super::Op::Alloc { .. } |
+            super::Op::Free { .. } => return Err(Error::Encoding(String::from("RapidBin has no allocation events"))),
+        });
+
+        let thread_id = check_bits(thread_id, THREAD_NUM_BITS, "too many threads")?;
+        let op_id = check_bits(op_id, OP_NUM_BITS, "operation id out of range")?;
+        let location_id = check_bits(location_id, LOC_NUM_BITS, "too many locations")?;
+        let decor = check_bits(decor, DECOR_NUM_BITS, "too many variables, locks or threads")?;
 
-        (thread_id << THREAD_BIT_OFFSET) |
+        Ok((thread_id << THREAD_BIT_OFFSET) |
             (op_id << OP_BIT_OFFSET) |
             (decor << DECOR_BIT_OFFSET) |
-            (location_id << LOC_BIT_OFFSET)
+            (location_id << LOC_BIT_OFFSET))
     }
 
-    pub fn push_event(&mut self, event: &Event) {
-        let binary_event = self.convert_event(event);
+    // Variables and locks in freed memory get a new identifier once the memory is reused
+    fn free(&mut self, addr: usize, size: usize) {
+        let end = addr.saturating_add(size);
+        let variables: Vec<_> = self.memory_map.range((addr, 0)..(end, 0)).map(|(v, _)| *v).collect();
+        for variable in variables {
+            self.memory_map.remove(&variable);
+        }
+        let locks: Vec<_> = self.lock_map.range(addr..end).map(|(l, _)| *l).collect();
+        for lock in locks {
+            self.lock_map.remove(&lock);
+        }
+    }
+
+    pub fn push_event(&mut self, event: &Event) -> Result<(), Error> {
+        match event.op {
+            Op::Alloc { .. } => return Ok(()),
+            Op::Free { addr, size } => {
+                self.free(addr, size);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let binary_event = self.convert_event(event)?;
         self.binary_trace.push(binary_event);
         self.event_counter += 1;
+        Ok(())
+    }
+
+    /// The `(fidx, iidx)` locations of the trace and the IDs they are encoded as.
+    pub fn location_map(&self) -> &HashMap<(usize, usize), i16> {
+        &self.location_map
+    }
+
+    /// Writes the location map as a tab separated text file with one `id fidx iidx` line per
+    /// location, which [`read_location_table`] reads back.
+    ///
+    /// RapidBin only stores the IDs, so the table is needed to symbolize a decoded trace.
+    pub fn write_location_table<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut locations: Vec<_> = self.location_map.iter().map(|(loc, id)| (*id, *loc)).collect();
+        locations.sort_unstable();
+
+        writeln!(writer, "{LOCATION_TABLE_HEADER}")?;
+        for (id, (fidx, iidx)) in locations {
+            writeln!(writer, "{id}\t{fidx}\t{iidx}")?;
+        }
+        Ok(())
     }
 
     pub fn build(self) -> Vec<u8> {
-        let mut output = Vec::with_capacity(self.binary_trace.len() * std::mem::size_of::<i64>() + 12);
+        let mut output = Vec::with_capacity(self.binary_trace.len() * std::mem::size_of::<i64>() + HEADER_SIZE);
 
         output.extend(self.thread_counter.to_be_bytes());
         output.extend(self.lock_counter.to_be_bytes());
@@ -151,11 +209,116 @@ impl BinaryTraceBuilder {
     }
 }
 
+impl Default for BinaryTraceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The counts stored at the start of a RapidBin trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceHeader {
+    pub threads: i16,
+    pub locks: i32,
+    pub variables: i32,
+    pub events: i64,
+}
+
+/// A decoded RapidBin trace.
+///
+/// RapidBin only stores the IDs the builder assigned, so the decoded events refer to
+/// those instead of the original values: addresses are variable IDs with `n = 1`, locks
+/// are lock IDs, threads are thread IDs and `loc` is `(location_id, 0)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryTrace {
+    pub header: TraceHeader,
+    pub events: Vec<Event>,
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], Error> {
+    bytes
+        .get(offset..offset + N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| Error::Encoding(String::from("truncated RapidBin trace")))
+}
+
+fn decode_event(event: i64) -> Result<Event, Error> {
+    let t = ((event & THREAD_MASK) >> THREAD_BIT_OFFSET) as u32;
+    let op_id = ((event & OP_MASK) >> OP_BIT_OFFSET) as u8;
+    let decor = ((event & DECOR_MASK) >> DECOR_BIT_OFFSET) as usize;
+    let loc = ((event & LOC_MASK) >> LOC_BIT_OFFSET) as usize;
+
+    let op = match op_id {
+        0 => Op::Aquire { lock: decor },
+        1 => Op::Release { lock: decor },
+        2 => Op::Read { addr: decor, n: 1 },
+        3 => Op::Write { addr: decor, n: 1 },
+        4 => Op::Fork { tid: decor as u32 },
+        5 => Op::Join { tid: decor as u32 },
+        6 => Op::Begin,
+        7 => Op::End,
+        8 => Op::Request { lock: decor },
+        _ => return Err(Error::Encoding(format!("unknown operation id {op_id}"))),
+    };
+
+    Ok(Event { t, op, loc: (loc, 0) })
+}
+
+/// Reads a location table written by [`BinaryTraceBuilder::write_location_table`].
+pub fn read_location_table<R: BufRead>(reader: R) -> Result<HashMap<(usize, usize), i16>, Error> {
+    let mut map = HashMap::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || Error::Encoding(format!("invalid location table entry in line {}", number + 1));
+        let fields: Vec<_> = line.split('\t').collect();
+        let [id, fidx, iidx] = fields[..] else {
+            return Err(invalid());
+        };
+        let id: i16 = id.parse().map_err(|_| invalid())?;
+        let fidx: usize = fidx.parse().map_err(|_| invalid())?;
+        let iidx: usize = iidx.parse().map_err(|_| invalid())?;
+        map.insert((fidx, iidx), id);
+    }
+
+    Ok(map)
+}
+
+/// Decodes a trace produced by [`BinaryTraceBuilder::build`].
+pub fn read_binary_trace(bytes: &[u8]) -> Result<BinaryTrace, Error> {
+    let header = TraceHeader {
+        threads: i16::from_be_bytes(read_bytes(bytes, 0)?) & NUMBER_OF_TRHEADS_MASK,
+        locks: i32::from_be_bytes(read_bytes(bytes, 2)?) & NUMBER_OF_LOCKS_MASK,
+        variables: i32::from_be_bytes(read_bytes(bytes, 6)?) & NUMBER_OF_VARS_MASK,
+        events: i64::from_be_bytes(read_bytes(bytes, 10)?) & NUMBER_OF_EVENTS_MASK,
+    };
+
+    let body = &bytes[HEADER_SIZE..];
+    if !body.len().is_multiple_of(8) || (body.len() / 8) as u64 != header.events as u64 {
+        return Err(Error::Encoding(format!(
+            "header announces {} events, but the trace contains {} bytes of events",
+            header.events,
+            body.len()
+        )));
+    }
+
+    let events = body
+        .chunks_exact(8)
+        .map(|chunk| decode_event(i64::from_be_bytes(chunk.try_into().unwrap())))
+        .collect::<Result<_, _>>()?;
+
+    Ok(BinaryTrace { header, events })
+}
+
 #[cfg(test)]
 mod test {
-    use crate::tracing::{Event, Op};
+    use crate::{error::Error, tracing::{Event, Op}};
 
-    use super::{BinaryTraceBuilder, THREAD_BIT_OFFSET, OP_BIT_OFFSET, DECOR_BIT_OFFSET, LOC_BIT_OFFSET};
+    use super::{read_binary_trace, read_location_table, BinaryTraceBuilder, THREAD_NUM_BITS, THREAD_BIT_OFFSET, OP_BIT_OFFSET, DECOR_BIT_OFFSET, LOC_BIT_OFFSET};
 
     #[test]
     fn test_event_conversion() {
@@ -165,6 +328,75 @@ mod test {
             (3 << OP_BIT_OFFSET) |
             (0 << DECOR_BIT_OFFSET) |
             (0 << LOC_BIT_OFFSET);
-        assert_eq!(builder.convert_event(&event), binary_event)
+        assert_eq!(builder.convert_event(&event).unwrap(), binary_event)
+    }
+
+    #[test]
+    fn test_thread_overflow() {
+        let mut builder = BinaryTraceBuilder::new();
+        for t in 0..(1 << THREAD_NUM_BITS) {
+            let event = Event {t, op: Op::Read { addr: 0, n: 1 }, loc: (0, 0)};
+            assert!(builder.push_event(&event).is_ok());
+        }
+        let event = Event {t: 1 << THREAD_NUM_BITS, op: Op::Read { addr: 0, n: 1 }, loc: (0, 0)};
+        assert!(matches!(builder.push_event(&event), Err(Error::TraceOverflow(_))));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reused_address() {
+        let mut builder = BinaryTraceBuilder::new();
+        let events = [
+            Event {t: 0, op: Op::Alloc { addr: 64, size: 16 }, loc: (0, 0)},
+            Event {t: 0, op: Op::Write { addr: 72, n: 4 }, loc: (0, 1)},
+            Event {t: 0, op: Op::Free { addr: 64, size: 16 }, loc: (0, 2)},
+            Event {t: 1, op: Op::Alloc { addr: 64, size: 16 }, loc: (1, 0)},
+            Event {t: 1, op: Op::Write { addr: 72, n: 4 }, loc: (1, 1)},
+        ];
+        for event in &events {
+            builder.push_event(event).unwrap();
+        }
+
+        let trace = read_binary_trace(&builder.build()).unwrap();
+        assert_eq!(trace.header.variables, 2);
+        assert_eq!(trace.events.len(), 2);
+        assert_eq!(trace.events[1].op, Op::Write { addr: 1, n: 1 });
+    }
+
+    #[test]
+    fn test_read_binary_trace() {
+        let mut builder = BinaryTraceBuilder::new();
+        let events = [
+            Event {t: 7, op: Op::Fork { tid: 9 }, loc: (1, 2)},
+            Event {t: 9, op: Op::Request { lock: 300 }, loc: (3, 4)},
+            Event {t: 9, op: Op::Aquire { lock: 300 }, loc: (3, 5)},
+            Event {t: 9, op: Op::Write { addr: 100, n: 4 }, loc: (3, 6)},
+            Event {t: 9, op: Op::Release { lock: 300 }, loc: (3, 7)},
+            Event {t: 7, op: Op::Read { addr: 100, n: 4 }, loc: (1, 8)},
+            Event {t: 7, op: Op::Join { tid: 9 }, loc: (1, 9)},
+        ];
+        for event in &events {
+            builder.push_event(event).unwrap();
+        }
+        let mut table = Vec::new();
+        builder.write_location_table(&mut table).unwrap();
+        assert_eq!(read_location_table(table.as_slice()).unwrap(), *builder.location_map());
+
+        let trace = read_binary_trace(&builder.build()).unwrap();
+        assert_eq!(trace.header.threads, 2);
+        assert_eq!(trace.header.locks, 1);
+        assert_eq!(trace.header.variables, 1);
+        assert_eq!(trace.header.events, 7);
+        assert_eq!(trace.events[0], Event {t: 0, op: Op::Fork { tid: 1 }, loc: (0, 0)});
+        assert_eq!(trace.events[3], Event {t: 1, op: Op::Write { addr: 0, n: 1 }, loc: (3, 0)});
+        assert_eq!(trace.events[5], Event {t: 0, op: Op::Read { addr: 0, n: 1 }, loc: (5, 0)});
+    }
+
+    #[test]
+    fn test_read_truncated_trace() {
+        let mut builder = BinaryTraceBuilder::new();
+        builder.push_event(&Event {t: 0, op: Op::Read { addr: 0, n: 1 }, loc: (0, 0)}).unwrap();
+        let bytes = builder.build();
+        assert!(matches!(read_binary_trace(&bytes[..bytes.len() - 1]), Err(Error::Encoding(_))));
+        assert!(matches!(read_binary_trace(&bytes[..4]), Err(Error::Encoding(_))));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, io::Write};
+
+use crate::error::Error;
+
+use super::{Event, Op};
+
+pub(super) fn decor(op: &Op) -> String {
+    match op {
+        Op::Read { addr, n } | Op::Write { addr, n } if *n == 1 => format!("V{addr}"),
+        Op::Read { addr, n } | Op::Write { addr, n } => format!("V{addr}+{n}"),
+        Op::Aquire { lock } | Op::Request { lock } | Op::Release { lock } => format!("L{lock}"),
+        Op::Fork { tid } | Op::Join { tid } => format!("T{tid}"),
+        Op::Begin | Op::End => String::new(),
+        Op::Alloc { addr, size } | Op::Free { addr, size } => format!("A{addr}+{size}"),
+    }
+}
+
+// The suffix of a variable or lock name in the given generation, none for the first one
+fn generation(generation: u32) -> String {
+    match generation {
+        0 => String::new(),
+        generation => format!("_{generation}"),
+    }
+}
+
+/// Names the variables and locks of a trace in its order.
+///
+/// Freed memory is often reused for unrelated objects, so every Free starts a new generation of
+/// the variables and locks in it, which is appended to their name (e.g., `V16_1`). Like in
+/// RapidBin traces, unrelated lifetimes at the same address are then different variables and locks.
+#[derive(Default)]
+struct Names {
+    variables: BTreeMap<(usize, usize), u32>,
+    locks: BTreeMap<usize, u32>,
+}
+
+impl Names {
+    fn decor(&mut self, op: &Op) -> String {
+        match *op {
+            Op::Read { addr, n } | Op::Write { addr, n } => {
+                let generation = generation(*self.variables.entry((addr, n)).or_default());
+                match n {
+                    1 => format!("V{addr}{generation}"),
+                    n => format!("V{addr}{generation}+{n}"),
+                }
+            }
+            Op::Aquire { lock } | Op::Request { lock } | Op::Release { lock } => {
+                format!("L{lock}{}", generation(*self.locks.entry(lock).or_default()))
+            }
+            Op::Free { addr, size } => {
+                let end = addr.saturating_add(size);
+                for (_, generation) in self.variables.range_mut((addr, 0)..(end, 0)) {
+                    *generation += 1;
+                }
+                for (_, generation) in self.locks.range_mut(addr..end) {
+                    *generation += 1;
+                }
+                decor(op)
+            }
+            _ => decor(op),
+        }
+    }
+}
+
+pub(super) fn name(op: &Op) -> &'static str {
+    match op {
+        Op::Read { .. } => "r",
+        Op::Write { .. } => "w",
+        Op::Aquire { .. } => "acq",
+        Op::Request { .. } => "req",
+        Op::Release { .. } => "rel",
+        Op::Fork { .. } => "fork",
+        Op::Join { .. } => "join",
+        Op::Begin => "begin",
+        Op::End => "end",
+        Op::Alloc { .. } => "alloc",
+        Op::Free { .. } => "free",
+    }
+}
+
+/// Writes `events` in RAPID's STD format, one `T<t>|<op>(<decor>)|<fidx>:<iidx>` line per event.
+///
+/// RAPID does not know Alloc and Free events, so they are left out. Variables and locks in freed
+/// memory get a new name instead, such as `V16_1` for the second variable at address 16.
+pub fn write_std_trace<'a, W: Write>(
+    events: impl IntoIterator<Item = &'a Event>,
+    mut writer: W,
+) -> Result<(), Error> {
+    let mut names = Names::default();
+    for Event { t, op, loc } in events {
+        let decor = names.decor(op);
+        if let Op::Alloc { .. } | Op::Free { .. } = op {
+            continue;
+        }
+        writeln!(writer, "T{t}|{}({decor})|{}:{}", name(op), loc.0, loc.1)?;
+    }
+    Ok(())
+}
+
+/// Writes `events` as CSV with a `thread,op,decor,fidx,iidx` header, with the same names as
+/// [`write_std_trace`].
+pub fn write_csv_trace<'a, W: Write>(
+    events: impl IntoIterator<Item = &'a Event>,
+    mut writer: W,
+) -> Result<(), Error> {
+    let mut names = Names::default();
+    writeln!(writer, "thread,op,decor,fidx,iidx")?;
+    for Event { t, op, loc } in events {
+        writeln!(writer, "{t},{},{},{},{}", name(op), names.decor(op), loc.0, loc.1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::{write_csv_trace, write_std_trace};
+
+    #[test]
+    fn test_write_std_trace() {
+        let events = [
+            Event { t: 0, op: Op::Aquire { lock: 3 }, loc: (1, 2) },
+            Event { t: 0, op: Op::Write { addr: 16, n: 4 }, loc: (1, 3) },
+            Event { t: 1, op: Op::Join { tid: 2 }, loc: (5, 0) },
+        ];
+        let mut std = Vec::new();
+        write_std_trace(&events, &mut std).unwrap();
+        assert_eq!(String::from_utf8(std).unwrap(), "T0|acq(L3)|1:2\nT0|w(V16+4)|1:3\nT1|join(T2)|5:0\n");
+
+        let mut csv = Vec::new();
+        write_csv_trace(&events[..1], &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "thread,op,decor,fidx,iidx\n0,acq,L3,1,2\n");
+    }
+
+    #[test]
+    fn test_reused_address() {
+        // A variable and a lock in an allocation that is freed, and the same addresses reused
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            events.extend([
+                Event { t: 0, op: Op::Alloc { addr: 16, size: 8 }, loc: (0, 0) },
+                Event { t: 0, op: Op::Aquire { lock: 20 }, loc: (0, 1) },
+                Event { t: 0, op: Op::Write { addr: 16, n: 4 }, loc: (0, 2) },
+                Event { t: 0, op: Op::Release { lock: 20 }, loc: (0, 3) },
+                Event { t: 0, op: Op::Free { addr: 16, size: 8 }, loc: (0, 4) },
+            ]);
+        }
+        events.push(Event { t: 1, op: Op::Read { addr: 24, n: 1 }, loc: (1, 0) });
+
+        let mut std = Vec::new();
+        write_std_trace(&events, &mut std).unwrap();
+        assert_eq!(
+            String::from_utf8(std).unwrap(),
+            concat!(
+                "T0|acq(L20)|0:1\nT0|w(V16+4)|0:2\nT0|rel(L20)|0:3\n",
+                "T0|acq(L20_1)|0:1\nT0|w(V16_1+4)|0:2\nT0|rel(L20_1)|0:3\n",
+                "T1|r(V24)|1:0\n",
+            )
+        );
+
+        let mut csv = Vec::new();
+        write_csv_trace(&events[5..], &mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().ends_with("0,free,A16+8,0,4\n1,r,V24,1,0\n"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Outputs for traces with timestamps, which RapidBin has no room for.
+
+use std::{collections::HashMap, io::Write};
+
+use crate::error::Error;
+
+use super::{
+    text::{decor, name},
+    Event, Op,
+};
+
+// The timestamp and location an open span started at
+type Start = (u64, (usize, usize));
+
+fn write_span<W: Write>(
+    writer: &mut W,
+    separator: &str,
+    name: String,
+    t: u32,
+    (start, loc): Start,
+    end: u64,
+) -> Result<(), Error> {
+    write!(
+        writer,
+        r#"{separator}{{"name":"{name}","ph":"X","ts":{start},"dur":{},"pid":0,"tid":{t},"args":{{"loc":"{}:{}"}}}}"#,
+        end - start,
+        loc.0,
+        loc.1
+    )?;
+    Ok(())
+}
+
+/// Writes the events that have a timestamp in the Trace Event Format of Chrome's `about:tracing`
+/// and Perfetto, with one track per thread.
+///
+/// Lock waits (from Request to Aquire), critical sections and atomic regions become spans, all
+/// other events are instants. Spans that are still open at the end of the trace, such as the
+/// waits of deadlocked threads, last until the last timestamp. Timestamps are in microseconds
+/// since the first one.
+pub fn write_timeline<W: Write>(events: &[Event], timestamps: &[Option<u64>], mut writer: W) -> Result<(), Error> {
+    let timed: Vec<_> = events
+        .iter()
+        .zip(timestamps)
+        .filter_map(|(event, timestamp)| Some((event, (*timestamp)?)))
+        .collect();
+    let origin = timed.iter().map(|(_, timestamp)| *timestamp).min().unwrap_or(0);
+
+    let mut waits: HashMap<(u32, usize), Start> = HashMap::new();
+    let mut holds: HashMap<(u32, usize), Start> = HashMap::new();
+    // The nesting depth and start of the open atomic region of each thread
+    let mut regions: HashMap<u32, (usize, Start)> = HashMap::new();
+
+    write!(writer, r#"{{"displayTimeUnit":"ms","traceEvents":["#)?;
+    let mut separator = "";
+    for (Event { t, op, loc }, timestamp) in &timed {
+        let ts = timestamp - origin;
+        match *op {
+            Op::Request { lock } => {
+                waits.insert((*t, lock), (ts, *loc));
+            }
+            Op::Aquire { lock } => {
+                if let Some(start) = waits.remove(&(*t, lock)) {
+                    write_span(&mut writer, separator, format!("wait L{lock}"), *t, start, ts)?;
+                    separator = ",";
+                }
+                holds.insert((*t, lock), (ts, *loc));
+            }
+            Op::Release { lock } => {
+                if let Some(start) = holds.remove(&(*t, lock)) {
+                    write_span(&mut writer, separator, format!("hold L{lock}"), *t, start, ts)?;
+                    separator = ",";
+                }
+            }
+            // Only the outermost region is shown, like in the atomicity analysis
+            Op::Begin => {
+                regions.entry(*t).or_insert((0, (ts, *loc))).0 += 1;
+            }
+            Op::End => {
+                if let Some((depth, start)) = regions.get_mut(t) {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        write_span(&mut writer, separator, String::from("atomic"), *t, *start, ts)?;
+                        separator = ",";
+                        regions.remove(t);
+                    }
+                }
+            }
+            _ => {
+                write!(
+                    writer,
+                    r#"{separator}{{"name":"{}({})","ph":"i","s":"t","ts":{ts},"pid":0,"tid":{t},"args":{{"loc":"{}:{}"}}}}"#,
+                    name(op),
+                    decor(op),
+                    loc.0,
+                    loc.1
+                )?;
+                separator = ",";
+            }
+        }
+    }
+
+    let end = timed.iter().map(|(_, timestamp)| timestamp - origin).max().unwrap_or(0);
+    let mut open: Vec<_> = waits
+        .into_iter()
+        .map(|((t, lock), start)| (format!("wait L{lock}"), t, start))
+        .chain(holds.into_iter().map(|((t, lock), start)| (format!("hold L{lock}"), t, start)))
+        .chain(regions.into_iter().map(|(t, (_, start))| (String::from("atomic"), t, start)))
+        .collect();
+    open.sort_by_key(|(_, t, (start, _))| (*start, *t));
+    for (name, t, start) in open {
+        write_span(&mut writer, separator, name, t, start, end)?;
+        separator = ",";
+    }
+    writeln!(writer, "]}}")?;
+    Ok(())
+}
+
+/// Writes the timestamps of `events` as JSON, to accompany the RapidBin trace of `events`.
+///
+/// Like RapidBin, Alloc and Free events are left out, so the n-th timestamp belongs to the n-th
+/// event of the RapidBin trace. Timestamps are in microseconds since `origin`, or null for
+/// events recorded while timestamps were disabled.
+pub fn write_metadata<W: Write>(events: &[Event], timestamps: &[Option<u64>], mut writer: W) -> Result<(), Error> {
+    let timestamps: Vec<_> = events
+        .iter()
+        .zip(timestamps)
+        .filter(|(event, _)| !matches!(event.op, Op::Alloc { .. } | Op::Free { .. }))
+        .map(|(_, timestamp)| *timestamp)
+        .collect();
+    let origin = timestamps.iter().flatten().min().copied().unwrap_or(0);
+
+    write!(
+        writer,
+        r#"{{"clock":"us","origin":{origin},"events":{},"timestamps":["#,
+        timestamps.len()
+    )?;
+    for (i, timestamp) in timestamps.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        match timestamp {
+            Some(timestamp) => write!(writer, "{separator}{}", timestamp - origin)?,
+            None => write!(writer, "{separator}null")?,
+        }
+    }
+    writeln!(writer, "]}}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tracing::{Event, Op};
+
+    use super::{write_metadata, write_timeline};
+
+    fn event(t: u32, op: Op) -> Event {
+        Event { t, op, loc: (t as usize, 0) }
+    }
+
+    #[test]
+    fn test_write_timeline() {
+        let events = [
+            event(0, Op::Request { lock: 1 }),
+            event(0, Op::Aquire { lock: 1 }),
+            event(1, Op::Request { lock: 1 }),
+            event(0, Op::Write { addr: 8, n: 4 }),
+            event(0, Op::Release { lock: 1 }),
+        ];
+        let timestamps = [Some(100), Some(110), Some(115), None, Some(150)];
+
+        let mut timeline = Vec::new();
+        write_timeline(&events, &timestamps, &mut timeline).unwrap();
+        assert_eq!(
+            String::from_utf8(timeline).unwrap(),
+            concat!(
+                r#"{"displayTimeUnit":"ms","traceEvents":["#,
+                r#"{"name":"wait L1","ph":"X","ts":0,"dur":10,"pid":0,"tid":0,"args":{"loc":"0:0"}},"#,
+                r#"{"name":"hold L1","ph":"X","ts":10,"dur":40,"pid":0,"tid":0,"args":{"loc":"0:0"}},"#,
+                r#"{"name":"wait L1","ph":"X","ts":15,"dur":35,"pid":0,"tid":1,"args":{"loc":"1:0"}}"#,
+                "]}\n"
+            )
+        );
+
+        let mut metadata = Vec::new();
+        write_metadata(&events, &timestamps, &mut metadata).unwrap();
+        assert_eq!(
+            String::from_utf8(metadata).unwrap(),
+            "{\"clock\":\"us\",\"origin\":100,\"events\":5,\"timestamps\":[0,10,15,null,50]}\n"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::{collections::BTreeSet, str::FromStr};
+
+use crate::error::Error;
+
+use super::{Event, Op};
+
+/// How memory accesses are mapped to the variables of a trace.
+///
+/// Analyses and exporters consider two accesses to conflict only if they have the same
+/// `(addr, n)` variable. With any granularity but [`Granularity::Exact`], an access is split
+/// into the variables it overlaps, so that mixed-size accesses to the same memory conflict.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Granularity {
+    /// Every distinct `(addr, n)` pair is a variable of its own.
+    #[default]
+    Exact,
+    /// Every byte is a variable.
+    Byte,
+    /// Every aligned word of `size` bytes is a variable. `size` must be a power of two, see
+    /// [`Granularity::word`].
+    Word { size: usize },
+    /// The address space is split at the start and end of every access in the trace, and
+    /// every resulting interval is a variable.
+    ///
+    /// The intervals depend on the whole trace, so analyses that run while the trace is
+    /// recorded use [`Granularity::Byte`] instead, which finds the same conflicts.
+    Interval,
+}
+
+impl Granularity {
+    /// Word granularity with words of `size` bytes, which must be a power of two.
+    pub fn word(size: usize) -> Result<Self, Error> {
+        if size.is_power_of_two() {
+            Ok(Granularity::Word { size })
+        } else {
+            Err(Error::Encoding(format!("invalid word size {size}, must be a power of two")))
+        }
+    }
+
+    /// Returns the granularity if it is valid, which only a [`Granularity::Word`] may not be.
+    pub fn validate(self) -> Result<Self, Error> {
+        match self {
+            Granularity::Word { size } => Self::word(size),
+            granularity => Ok(granularity),
+        }
+    }
+
+    /// The granularity used for events that are processed as they are recorded.
+    pub(crate) fn online(self) -> Self {
+        match self {
+            Granularity::Interval => Granularity::Byte,
+            granularity => granularity,
+        }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Encoding(format!("invalid granularity '{s}'"));
+        match s.split_once(':') {
+            None if s == "exact" => Ok(Granularity::Exact),
+            None if s == "byte" => Ok(Granularity::Byte),
+            None if s == "word" => Ok(Granularity::Word {
+                size: std::mem::size_of::<usize>(),
+            }),
+            None if s == "interval" => Ok(Granularity::Interval),
+            Some(("word", size)) => size.parse().map_err(|_| invalid()).and_then(Self::word),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Calls `f` with every variable the access `[addr, addr + n)` overlaps
+fn split_access(
+    addr: usize,
+    n: usize,
+    granularity: Granularity,
+    boundaries: &BTreeSet<usize>,
+    mut f: impl FnMut(usize, usize),
+) {
+    let end = addr.saturating_add(n);
+    match granularity {
+        _ if n == 0 => f(addr, n),
+        Granularity::Exact => f(addr, n),
+        Granularity::Byte => (addr..end).for_each(|byte| f(byte, 1)),
+        Granularity::Word { size } => {
+            // The last word may end at the end of the address space
+            let mut word = Some(addr & !(size - 1));
+            while let Some(start) = word.filter(|start| *start < end) {
+                f(start, size);
+                word = start.checked_add(size);
+            }
+        }
+        Granularity::Interval => {
+            let mut start = addr;
+            for boundary in boundaries.range(addr.saturating_add(1)..end).chain([&end]) {
+                f(start, boundary - start);
+                start = *boundary;
+            }
+        }
+    }
+}
+
+fn split_event(
+    event: &Event,
+    granularity: Granularity,
+    boundaries: &BTreeSet<usize>,
+    out: &mut Vec<Event>,
+) {
+    match event.op {
+        Op::Read { addr, n } => split_access(addr, n, granularity, boundaries, |addr, n| {
+            out.push(Event {
+                op: Op::Read { addr, n },
+                ..*event
+            })
+        }),
+        Op::Write { addr, n } => split_access(addr, n, granularity, boundaries, |addr, n| {
+            out.push(Event {
+                op: Op::Write { addr, n },
+                ..*event
+            })
+        }),
+        _ => out.push(*event),
+    }
+}
+
+fn boundaries(events: &[Event], granularity: Granularity) -> BTreeSet<usize> {
+    let mut boundaries = BTreeSet::new();
+    if granularity == Granularity::Interval {
+        for event in events {
+            if let Op::Read { addr, n } | Op::Write { addr, n } = event.op {
+                boundaries.insert(addr);
+                boundaries.insert(addr.saturating_add(n));
+            }
+        }
+    }
+    boundaries
+}
+
+/// Splits every access of `events` into the variables it overlaps.
+pub fn split_events(events: &[Event], granularity: Granularity) -> Vec<Event> {
+    if granularity == Granularity::Exact {
+        return events.to_vec();
+    }
+
+    let boundaries = boundaries(events, granularity);
+    let mut output = Vec::with_capacity(events.len());
+    for event in events {
+        split_event(event, granularity, &boundaries, &mut output);
+    }
+    output
+}
+
+/// Like [`split_events`], every split access keeps the timestamp of the original one.
+pub(crate) fn split_timed_events(
+    events: &[Event],
+    timestamps: &[Option<u64>],
+    granularity: Granularity,
+) -> (Vec<Event>, Vec<Option<u64>>) {
+    let boundaries = boundaries(events, granularity);
+    let mut output = (Vec::with_capacity(events.len()), Vec::with_capacity(events.len()));
+    for (event, timestamp) in events.iter().zip(timestamps) {
+        split_event(event, granularity, &boundaries, &mut output.0);
+        output.1.resize(output.0.len(), *timestamp);
+    }
+    output
+}
+
+/// Splits a single access, for granularities that do not depend on the rest of the trace.
+pub(crate) fn split_online(event: &Event, granularity: Granularity) -> Vec<Event> {
+    let mut output = Vec::with_capacity(1);
+    split_event(event, granularity.online(), &BTreeSet::new(), &mut output);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        analysis::hb,
+        tracing::{Event, Op},
+    };
+
+    use super::{split_events, Granularity};
+
+    fn accesses(events: &[Event]) -> Vec<(usize, usize)> {
+        events
+            .iter()
+            .filter_map(|e| match e.op {
+                Op::Read { addr, n } | Op::Write { addr, n } => Some((addr, n)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_granularities() {
+        let events = [
+            Event {
+                t: 0,
+                op: Op::Write { addr: 100, n: 4 },
+                loc: (0, 0),
+            },
+            Event {
+                t: 1,
+                op: Op::Read { addr: 101, n: 1 },
+                loc: (1, 0),
+            },
+        ];
+
+        assert_eq!(
+            accesses(&split_events(&events, Granularity::Exact)),
+            [(100, 4), (101, 1)]
+        );
+        assert_eq!(
+            accesses(&split_events(&events, Granularity::Byte)),
+            [(100, 1), (101, 1), (102, 1), (103, 1), (101, 1)]
+        );
+        assert_eq!(
+            accesses(&split_events(&events, Granularity::Word { size: 8 })),
+            [(96, 8), (96, 8)]
+        );
+        assert_eq!(
+            accesses(&split_events(&events, Granularity::Interval)),
+            [(100, 1), (101, 1), (102, 2), (101, 1)]
+        );
+
+        let last = [Event {
+            t: 0,
+            op: Op::Write { addr: usize::MAX - 3, n: 2 },
+            loc: (0, 0),
+        }];
+        assert_eq!(
+            accesses(&split_events(&last, Granularity::Word { size: 8 })),
+            [(usize::MAX - 7, 8)]
+        );
+        assert_eq!(
+            accesses(&split_events(&last, Granularity::Interval)),
+            [(usize::MAX - 3, 2)]
+        );
+        // The end of the access saturates at the end of the address space
+        let end = [Event {
+            t: 0,
+            op: Op::Read { addr: usize::MAX, n: 1 },
+            loc: (0, 0),
+        }];
+        assert_eq!(
+            accesses(&split_events(&end, Granularity::Interval)),
+            [(usize::MAX, 0)]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "word:8".parse::<Granularity>().unwrap(),
+            Granularity::Word { size: 8 }
+        );
+        assert_eq!(
+            "interval".parse::<Granularity>().unwrap(),
+            Granularity::Interval
+        );
+        assert!("word:3".parse::<Granularity>().is_err());
+        assert!(Granularity::Word { size: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_mixed_size_race() {
+        let events = [
+            Event {
+                t: 0,
+                op: Op::Fork { tid: 1 },
+                loc: (0, 0),
+            },
+            Event {
+                t: 0,
+                op: Op::Write { addr: 100, n: 4 },
+                loc: (0, 1),
+            },
+            Event {
+                t: 1,
+                op: Op::Read { addr: 101, n: 1 },
+                loc: (1, 0),
+            },
+        ];
+
+        assert!(hb::detect(&split_events(&events, Granularity::Exact)).is_empty());
+        let races = hb::detect(&split_events(&events, Granularity::Interval));
+        assert_eq!(races.len(), 1);
+        assert_eq!(races[0].variable, (101, 1));
+    }
+}
@@ -1,78 +1,196 @@
-use crate::{console_log, tracing::{self, Op}};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tracing::UNKNOWN_LOCATION;
+#[cfg(feature = "log-events")]
+use crate::console_log;
+use crate::tracing::{self, Op, TracerGuard};
+
+// Logs an event as the hooks receive it, only with the `log-events` feature
+macro_rules! log_event {
+    ($($t:tt)*) => {
+        #[cfg(feature = "log-events")]
+        console_log!($($t)*);
+    };
+}
+
+// The following functions are intrinsics: the instrumenter inserts a call to the
+// corresponding event hook, with the location of the caller, before every call to them.
+// They must neither be inlined nor optimized away, otherwise there is no call to find.
+// Native code is not instrumented, so there they record their event themselves.
 
 #[no_mangle]
-pub extern "C" fn start_lock(_lock_id: usize) {
-    // Shold resolve to a call to `request_event`
-    //request_event(_lock_id, 0, 0);
+#[inline(never)]
+pub extern "C" fn start_lock(lock_id: usize) {
+    // Resolves to a call to `request_event`
+    std::hint::black_box(lock_id);
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Request { lock: lock_id }, UNKNOWN_LOCATION);
 }
 
 #[no_mangle]
-pub extern "C" fn finish_lock(_lock_id: usize) {
-    // Should resolve to a call to `aquire_event`
-    //aquire_event(_lock_id, 0, 0);
+#[inline(never)]
+pub extern "C" fn finish_lock(lock_id: usize) {
+    // Resolves to a call to `aquire_event`
+    std::hint::black_box(lock_id);
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Aquire { lock: lock_id }, UNKNOWN_LOCATION);
 }
 
 #[no_mangle]
-pub extern "C" fn start_unlock(_lock_id: usize) {
+#[inline(never)]
+pub extern "C" fn start_unlock(lock_id: usize) {
+    // Resolves to a call to `release_event`
+    std::hint::black_box(lock_id);
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Release { lock: lock_id }, UNKNOWN_LOCATION);
+}
+
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn finish_unlock(lock_id: usize) {
     // TODO: Does this even have a tracing pendant???
+    std::hint::black_box(lock_id);
 }
 
 #[no_mangle]
-pub extern "C" fn finish_unlock(_lock_id: usize) {
-    // Should resolve to a call to `release_event`
-    //release_event(_lock_id, 0, 0);
+#[inline(never)]
+pub extern "C" fn spawn_thread(thread_id: u32) {
+    // Resolves to a call to `fork_event`
+    std::hint::black_box(thread_id);
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Fork { tid: thread_id }, UNKNOWN_LOCATION);
 }
 
 #[no_mangle]
-pub extern "C" fn spawn_thread(_thread_id: u32) {
-    // Should resolve to a call to `fork_event`
-    //fork_event(_thread_id, 0, 0);
+#[inline(never)]
+pub extern "C" fn join_thread(thread_id: u32) {
+    // Resolves to a call to `join_event`
+    std::hint::black_box(thread_id);
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Join { tid: thread_id }, UNKNOWN_LOCATION);
 }
 
 #[no_mangle]
-pub extern "C" fn join_thread(_thread_id: u32) {
-    // Should resolve to a call to `join_thread`
-    //join_event(_thread_id, 0, 0);
+#[inline(never)]
+pub extern "C" fn begin_atomic() {
+    // Resolves to a call to `begin_event`
+    std::hint::black_box(());
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::Begin, UNKNOWN_LOCATION);
 }
 
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn end_atomic() {
+    // Resolves to a call to `end_event`
+    std::hint::black_box(());
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::add_event(Op::End, UNKNOWN_LOCATION);
+}
+
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn thread_memory() {
+    // Resolves to a call to `thread_memory_event` with the thread's stack pointer and TLS block
+    std::hint::black_box(());
+}
+
+/// Called by the worker script right after it initialized the module. The shadow stack is
+/// still empty and this function has no frame on it, so the stack pointer is the top of the stack.
+#[no_mangle]
+pub extern "C" fn wasm_ca_thread_start() {
+    thread_memory();
+}
+
+// The hooks that follow are called from instrumented code. If the runtime itself or the allocator is
+// instrumented, the hooks are called again while they record an event. These inner calls are
+// dropped, as they would otherwise recurse, or deadlock on the trace.
+
 #[no_mangle]
 pub extern "C" fn read_event(addr: usize, n: usize, fidx: usize, iidx: usize) {
-    console_log!("Read Event: addr: {}, n: {}, fidx: {}, iidx: {}", addr, n, fidx, iidx);
-    tracing::add_event(Op::Read { addr, n }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Read Event: addr: {}, n: {}, fidx: {}, iidx: {}", addr, n, fidx, iidx);
+    tracing::record_event(Op::Read { addr, n }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn write_event(addr: usize, n: usize, fidx: usize, iidx: usize) {
-    console_log!("Write Event: addr: {}, n: {}, fidx: {}, iidx: {}", addr, n, fidx, iidx);
-    tracing::add_event(Op::Write { addr, n }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Write Event: addr: {}, n: {}, fidx: {}, iidx: {}", addr, n, fidx, iidx);
+    tracing::record_event(Op::Write { addr, n }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn aquire_event(lock_id: usize, fidx: usize, iidx: usize) {
-    console_log!("Aquire Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
-    tracing::add_event(Op::Aquire { lock: lock_id }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Aquire Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
+    tracing::record_event(Op::Aquire { lock: lock_id }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn request_event(lock_id: usize, fidx: usize, iidx: usize) {
-    console_log!("Request Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
-    tracing::add_event(Op::Request { lock: lock_id }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Request Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
+    tracing::record_event(Op::Request { lock: lock_id }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn release_event(lock_id: usize, fidx: usize, iidx: usize) {
-    console_log!("Release Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
-    tracing::add_event(Op::Release { lock: lock_id }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Release Event: lock: {}, fidx: {}, iidx: {}", lock_id, fidx, iidx);
+    tracing::record_event(Op::Release { lock: lock_id }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn fork_event(thread_id: u32, fidx: usize, iidx: usize) {
-    console_log!("Fork Event: lock: {}, fidx: {}, iidx: {}", thread_id, fidx, iidx);
-    tracing::add_event(Op::Fork { tid: thread_id }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Fork Event: lock: {}, fidx: {}, iidx: {}", thread_id, fidx, iidx);
+    tracing::record_event(Op::Fork { tid: thread_id }, (fidx, iidx));
 }
 
 #[no_mangle]
 pub extern "C" fn join_event(thread_id: u32, fidx: usize, iidx: usize) {
-    console_log!("Join Event: lock: {}, fidx: {}, iidx: {}", thread_id, fidx, iidx);
-    tracing::add_event(Op::Join { tid: thread_id }, (fidx, iidx));
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Join Event: lock: {}, fidx: {}, iidx: {}", thread_id, fidx, iidx);
+    tracing::record_event(Op::Join { tid: thread_id }, (fidx, iidx));
+}
+
+#[no_mangle]
+pub extern "C" fn begin_event(fidx: usize, iidx: usize) {
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Begin Event: fidx: {}, iidx: {}", fidx, iidx);
+    tracing::record_event(Op::Begin, (fidx, iidx));
+}
+
+#[no_mangle]
+pub extern "C" fn end_event(fidx: usize, iidx: usize) {
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("End Event: fidx: {}, iidx: {}", fidx, iidx);
+    tracing::record_event(Op::End, (fidx, iidx));
+}
+
+#[no_mangle]
+pub extern "C" fn thread_memory_event(stack_top: usize, tls_base: usize, tls_size: usize) {
+    let Some(_guard) = TracerGuard::enter() else {
+        return;
+    };
+    log_event!("Thread Memory: stack top: {}, tls: {}+{}", stack_top, tls_base, tls_size);
+    tracing::private::set_thread_memory(stack_top, tls_base, tls_size);
 }
@@ -0,0 +1,91 @@
+//! Runs the commands of the `wasm-ca` binary on a small trace.
+
+use std::{path::PathBuf, process::Command};
+
+use wasm_ca_rs::tracing::{read_binary_trace, BinaryTraceBuilder, Event, Op};
+
+// Thread 9 writes under a lock, which thread 7 does not take before it reads. Its write after the
+// join is ordered.
+fn write_fixture(name: &str) -> PathBuf {
+    let events = [
+        Event { t: 7, op: Op::Fork { tid: 9 }, loc: (1, 0) },
+        Event { t: 9, op: Op::Request { lock: 300 }, loc: (2, 0) },
+        Event { t: 9, op: Op::Aquire { lock: 300 }, loc: (2, 1) },
+        Event { t: 9, op: Op::Write { addr: 100, n: 4 }, loc: (2, 2) },
+        Event { t: 9, op: Op::Release { lock: 300 }, loc: (2, 3) },
+        Event { t: 7, op: Op::Read { addr: 100, n: 4 }, loc: (1, 1) },
+        Event { t: 7, op: Op::Join { tid: 9 }, loc: (1, 2) },
+        Event { t: 7, op: Op::Write { addr: 100, n: 4 }, loc: (1, 3) },
+    ];
+    let mut builder = BinaryTraceBuilder::new();
+    for event in &events {
+        builder.push_event(event).unwrap();
+    }
+
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, builder.build()).unwrap();
+    path
+}
+
+fn wasm_ca(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-ca")).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_convert() {
+    let input = write_fixture("convert.bin");
+    let input = input.to_str().unwrap();
+
+    assert_eq!(
+        wasm_ca(&["convert", input, "-f", "std"]),
+        concat!(
+            "T0|fork(T1)|0:0\n",
+            "T1|req(L0)|1:0\n",
+            "T1|acq(L0)|2:0\n",
+            "T1|w(V0)|3:0\n",
+            "T1|rel(L0)|4:0\n",
+            "T0|r(V0)|5:0\n",
+            "T0|join(T1)|6:0\n",
+            "T0|w(V0)|7:0\n",
+        )
+    );
+
+    // Converting to RapidBin again yields the same trace
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("convert.out.bin");
+    wasm_ca(&["convert", input, "-f", "rapidbin", "-o", output.to_str().unwrap()]);
+    let original = read_binary_trace(&std::fs::read(input).unwrap()).unwrap();
+    let converted = read_binary_trace(&std::fs::read(&output).unwrap()).unwrap();
+    assert_eq!(converted, original);
+}
+
+#[test]
+fn test_races() {
+    let input = write_fixture("races.bin");
+
+    assert_eq!(
+        wasm_ca(&["races", input.to_str().unwrap()]),
+        "race on 0x0 (1 bytes): write by thread 1 at 3:0 and read by thread 0 at 5:0\n"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_write_error() {
+    let input = write_fixture("write_error.bin");
+
+    // Every write to /dev/full fails, which must not go unnoticed when the output is flushed
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-ca"))
+        .args(["events", input.to_str().unwrap()])
+        .stdout(std::fs::File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-ca"))
+        .args(["convert", input.to_str().unwrap(), "-f", "std", "-o", "/dev/full"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
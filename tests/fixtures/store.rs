@@ -0,0 +1,13 @@
+// Source of `store.wasm`, the debug module of the symbolization tests. Built with
+// rustc --target wasm32-unknown-unknown --crate-type cdylib -C panic=abort -C opt-level=1 -C debuginfo=2 store.rs
+#![no_std]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn store(ptr: *mut u32) {
+    ptr.write_volatile(42);
+}
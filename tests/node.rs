@@ -0,0 +1,17 @@
+use std::process::Command;
+
+// Runs `tests/node/thread.mjs` on the glue of the `node_thread` example, whose path is taken from
+// `WASM_CA_NODE_GLUE`. See the example for how to build it. The Node.js backend only supports the
+// ES module glue of `wasm-bindgen --target web` (see `src/thread/node.rs`), so this is not a
+// `wasm-bindgen-test --node` test, and `cargo test` only runs it with `--ignored`.
+#[test]
+#[ignore = "needs Node.js and the node_thread example built for wasm32"]
+fn test_node_thread() {
+    let glue = std::env::var("WASM_CA_NODE_GLUE").expect("WASM_CA_NODE_GLUE is not set");
+    let status = Command::new("node")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/node/thread.mjs"))
+        .arg(glue)
+        .status()
+        .expect("could not run node");
+    assert!(status.success());
+}
@@ -0,0 +1,16 @@
+// Spawns and joins one thread of the 'node_thread' example under Node.js. The
+// only argument is the glue generated for it by 'wasm-bindgen --target web'.
+import { readFile } from "node:fs/promises";
+import { resolve } from "node:path";
+import { pathToFileURL } from "node:url";
+
+const glue = resolve(process.argv[2]);
+const { default: init, spawn_and_join } = await import(pathToFileURL(glue).href);
+// Node.js cannot fetch 'file:' URLs, so the module is handed over as bytes
+await init({ module_or_path: await readFile(glue.replace(/\.js$/, "_bg.wasm")) });
+
+const result = spawn_and_join(41);
+if (result !== 42) {
+    throw new Error(`expected the thread to return 42, but it returned ${result}`);
+}
+console.log("spawned and joined a thread");
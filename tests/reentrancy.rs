@@ -0,0 +1,42 @@
+//! Simulates an instrumented allocator, whose accesses call the hooks while they record an event.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+use wasm_ca_rs::tracing::{export_trace, TraceFormat};
+
+extern "C" {
+    fn write_event(addr: usize, n: usize, fidx: usize, iidx: usize);
+}
+
+// Reports a write to every allocation, like an instrumented allocator writing its headers
+struct InstrumentedAllocator;
+
+unsafe impl GlobalAlloc for InstrumentedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        write_event(ptr as usize, 1, 1, 0);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        write_event(ptr as usize, 1, 1, 1);
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: InstrumentedAllocator = InstrumentedAllocator;
+
+#[test]
+fn test_instrumented_allocator() {
+    // Every recorded event grows the trace and logs, which allocates again
+    let boxes: Vec<_> = (0..100u64).map(Box::new).collect();
+    drop(boxes);
+
+    let mut csv = Vec::new();
+    export_trace(TraceFormat::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let allocations = csv.lines().filter(|line| line.ends_with(",1,0")).count();
+    let frees = csv.lines().filter(|line| line.ends_with(",1,1")).count();
+    assert!(allocations >= 100 && frees >= 100);
+}
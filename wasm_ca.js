@@ -1,5 +1,74 @@
+function is_node() {
+    return typeof process !== "undefined" && process.versions != null && process.versions.node != null;
+}
+
 function get_origin() {
+    if (is_node()) {
+        // There is no page in Node.js, so relative URLs are resolved against the working directory
+        const { pathToFileURL } = process.getBuiltinModule("node:url");
+        return pathToFileURL(process.cwd() + "/").href;
+    }
     return self.location.origin;
 }
 
-export { get_origin };
\ No newline at end of file
+function supports_module_workers() {
+    // The worker options are only read for their 'type' if module workers are supported
+    let supported = false;
+    const options = {
+        get type() {
+            supported = true;
+            return "module";
+        }
+    };
+    try {
+        new Worker("blob://", options).terminate();
+    } catch (_) {
+        // The worker itself is never meant to start
+    }
+    return supported;
+}
+
+function supports_node_workers() {
+    return typeof process.getBuiltinModule === "function"
+        && process.getBuiltinModule("node:worker_threads") != null;
+}
+
+function is_shared_memory(memory) {
+    return typeof SharedArrayBuffer !== "undefined" && memory.buffer instanceof SharedArrayBuffer;
+}
+
+// Wraps a 'worker_threads' worker in the subset of the Web Worker interface
+// used by the runtime: 'postMessage', 'terminate' and 'onmessage'.
+class NodeWorker {
+    constructor(script) {
+        const { Worker } = process.getBuiltinModule("node:worker_threads");
+        this.worker = new Worker(script, { eval: true });
+        this.onmessage = null;
+        this.worker.on("message", data => {
+            if (this.onmessage) {
+                this.onmessage({ data });
+            }
+        });
+    }
+
+    postMessage(msg) {
+        this.worker.postMessage(msg);
+    }
+
+    terminate() {
+        this.worker.terminate();
+    }
+}
+
+function spawn_node_worker(script) {
+    return new NodeWorker(script);
+}
+
+export {
+    is_node,
+    get_origin,
+    supports_module_workers,
+    supports_node_workers,
+    is_shared_memory,
+    spawn_node_worker,
+};
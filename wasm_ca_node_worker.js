@@ -0,0 +1,39 @@
+// Worker script for threads spawned by wasm-ca under Node.js. It is evaluated
+// as a 'worker_threads' worker and implements the same protocol as
+// 'wasm_ca_worker.js' does for browsers.
+const { parentPort } = require("node:worker_threads");
+
+// The runtime posts messages through the global scope, like in a WebWorker.
+globalThis.postMessage = msg => parentPort.postMessage(msg);
+
+let wasm = undefined;
+
+// Messages are handled one after another, such that a 'close' message which
+// arrives while the module is still initializing is not lost.
+let queue = Promise.resolve();
+parentPort.on("message", data => {
+    queue = queue.then(() => handle_message(data)).catch(e => console.error(e));
+});
+
+async function handle_message(data) {
+    if (data.type == "init") {
+        let {type, url, module, memory, task, stack_size} = data;
+        let {default: init} = await import(url);
+        if (typeof init !== "function") {
+            // Only the ES module glue of 'wasm-bindgen --target web' can be imported from a URL
+            // and initialized with the shared module and memory
+            throw new Error(`${url} is not glue generated with 'wasm-bindgen --target web'`);
+        }
+        wasm = await init({module_or_path: module, memory, thread_stack_size: stack_size});
+        // Reports the bounds of the new thread's stack while it is still empty
+        wasm.wasm_ca_thread_start();
+        wasm.handle_msg({type, task})
+    } else if (!wasm) {
+        console.warn("Wasm module has not been initialized. Ignoring message ...")
+    } else if (data.type == "close") {
+        wasm.__wbindgen_thread_destroy(); // Deallocate TLS and thread stack
+        parentPort.close();
+    } else {
+        wasm.handle_msg(data)
+    }
+}
@@ -0,0 +1,38 @@
+// Worker script for threads spawned by wasm-ca. By default it is loaded from a
+// 'blob:' URL. Pages with a strict Content-Security-Policy can host this file
+// themselves and point the runtime at it with 'set_worker_url'.
+console.log("JScript: initializing standalone worker")
+
+// Wait for the main thread to send us the shared module/memory. Once we've got
+// it, initialize it all with the 'wasm_bindgen' module
+let wasm = undefined;
+
+// Messages are handled one after another, such that a 'close' message which
+// arrives while the module is still initializing is not lost.
+let queue = Promise.resolve();
+self.onmessage = event => {
+    queue = queue.then(() => handle_message(event.data)).catch(e => console.error(e));
+}
+
+async function handle_message(data) {
+    if (data.type == "init") {
+        let {type, url, module, memory, task, stack_size} = data;
+        let {default: init} = await import(url);
+        if (typeof init !== "function") {
+            // Only the ES module glue of 'wasm-bindgen --target web' can be imported from a URL
+            // and initialized with the shared module and memory
+            throw new Error(`${url} is not glue generated with 'wasm-bindgen --target web'`);
+        }
+        wasm = await init({module_or_path: module, memory, thread_stack_size: stack_size});
+        // Reports the bounds of the new thread's stack while it is still empty
+        wasm.wasm_ca_thread_start();
+        wasm.handle_msg({type, task})
+    } else if (!wasm) {
+        console.warn("Wasm module has not been initialized. Ignoring message ...")
+    } else if (data.type == "close") {
+        wasm.__wbindgen_thread_destroy(); // Deallocate TLS and thread stack
+        self.close();
+    } else {
+        wasm.handle_msg(data)
+    }
+}